@@ -1,5 +1,38 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use lcs_rs::{self, compute, read_file_and_preprocess};
+use lcs_rs::{self, compute, read_file_and_preprocess, Corpus};
+#[cfg(feature = "parallel")]
+use std::cmp;
+
+/* Generates n pseudo-random files of the given length */
+fn random_files(n: usize, length: usize) -> Vec<(String, Vec<u8>)> {
+    let mut state = 0x2545f491u32;
+    let mut next_byte = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    };
+    (0..n)
+        .map(|i| {
+            (
+                format!("synthetic.{}", i),
+                (0..length).map(|_| next_byte()).collect(),
+            )
+        })
+        .collect()
+}
+
+/* Generates n small pseudo-random files of the given length, every one of which shares a
+ * chunk of its bytes with the next one so that there is something to find.
+ */
+fn synthetic_files(n: usize, length: usize) -> Vec<(String, Vec<u8>)> {
+    let mut files = random_files(n, length);
+    for i in 1..n {
+        let shared = files[i - 1].1[length / 2..].to_vec();
+        files[i].1[..shared.len()].copy_from_slice(&shared);
+    }
+    files
+}
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let string_files: Vec<String> = (1..11)
@@ -38,6 +71,46 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             });
         }
     }
+    {
+        //The total length grows linearly with the number of files, and so should the runtime
+        let mut mf = c.benchmark_group("many files");
+        mf.sample_size(10);
+        for &n in &[100, 200, 400, 800, 1600] {
+            let corpus = Corpus::from_documents(synthetic_files(n, 256)).unwrap();
+            mf.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &_n| {
+                b.iter(|| corpus.lcs(2).unwrap())
+            });
+        }
+    }
+    #[cfg(feature = "parallel")]
+    {
+        //A corpus built on a single thread gets its suffix array from SAIS, and its lcp array
+        //from Kasai's algorithm, while on more threads both are built in parallel
+        let mut sa = c.benchmark_group("suffix and lcp arrays");
+        sa.sample_size(10);
+        let pools = [
+            ("sais", 1),
+            ("parallel", cmp::max(2, rayon::current_num_threads())),
+        ];
+        let inputs = [
+            ("random", random_files(16, 1 << 18)),
+            ("shared halves", synthetic_files(16, 1 << 18)),
+        ];
+        for (input, documents) in &inputs {
+            for &(construction, threads) in &pools {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                let documents = documents
+                    .iter()
+                    .map(|(name, d)| (name.as_str(), d.as_slice()));
+                sa.bench_function(BenchmarkId::new(construction, input), |b| {
+                    b.iter(|| pool.install(|| Corpus::from_documents(documents.clone()).unwrap()))
+                });
+            }
+        }
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
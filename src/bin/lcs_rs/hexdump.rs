@@ -0,0 +1,168 @@
+use lcs_rs::{ComputeResult, Corpus};
+use std::collections::HashMap;
+
+const ROW_LEN: usize = 8;
+/* offset, hex bytes and ascii bytes of a single row */
+const COLUMN_WIDTH: usize = 10 + 3 * ROW_LEN + ROW_LEN + 2;
+
+const MATCHED: &str = "\x1b[32m";
+const DIVERGED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/* Prints the common substring of a result as a hexdump, side by side for every file it was
+ * found in, along with `context` bytes before and after it. The common substring is
+ * highlighted, as are context bytes which aren't the same in every file so it's easy to see
+ * where the files diverge. Rows lying entirely inside of the common substring are the same
+ * in every file, so all but the first and last are collapsed into a `*` line.
+ */
+pub struct Hexdump<'a> {
+    corpus: &'a Corpus,
+    docs: HashMap<&'a str, usize>,
+    context: usize,
+    color: bool,
+}
+
+impl<'a> Hexdump<'a> {
+    pub fn new(corpus: &'a Corpus, context: usize, color: bool) -> Hexdump<'a> {
+        let docs = (0..corpus.num_documents())
+            .map(|doc| (corpus.name(doc), doc))
+            .collect();
+        Hexdump {
+            corpus,
+            docs,
+            context,
+            color,
+        }
+    }
+
+    pub fn print(&self, res: &ComputeResult) {
+        let window = res.length + 2 * self.context;
+
+        // The first occurrence in each file and the bytes around it, None outside of the file
+        let columns: Vec<(usize, Vec<Option<u8>>)> = res
+            .occurrences
+            .iter()
+            .map(|(name, offs)| {
+                let doc = self.docs[name];
+                let start = offs[0] as isize - self.context as isize;
+                let bytes = self.corpus.document_bytes(
+                    doc,
+                    start.max(0) as usize,
+                    offs[0] + res.length + self.context,
+                );
+                let column = (0..window as isize)
+                    .map(|i| {
+                        let pos = start + i;
+                        if pos < 0 {
+                            None
+                        } else {
+                            bytes.get((pos - start.max(0)) as usize).cloned()
+                        }
+                    })
+                    .collect();
+                (offs[0], column)
+            })
+            .collect();
+
+        let header: Vec<String> = res
+            .occurrences
+            .iter()
+            .map(|(name, offs)| {
+                let title = format!("{} at {}", name, offs[0]);
+                format!("{:<w$.w$}", title, w = COLUMN_WIDTH)
+            })
+            .collect();
+        println!("{}", header.join("   ").trim_end());
+
+        let matched = self.context..self.context + res.length;
+        let rows = window.div_ceil(ROW_LEN);
+        let mut collapsed = false;
+        for row in 0..rows {
+            let from = row * ROW_LEN;
+            let to = from + ROW_LEN;
+            let inner = from >= matched.start && to <= matched.end;
+            let first = inner && (from < ROW_LEN || from - ROW_LEN < matched.start);
+            let last = inner && to + ROW_LEN > matched.end;
+            if inner && !first && !last {
+                if !collapsed {
+                    println!("*");
+                    collapsed = true;
+                }
+                continue;
+            }
+
+            // Rows past the start or end of every file are left out
+            if columns
+                .iter()
+                .all(|(_, column)| column[from..].iter().take(ROW_LEN).all(|b| b.is_none()))
+            {
+                continue;
+            }
+
+            let line: Vec<String> = columns
+                .iter()
+                .map(|(off, column)| self.row(&columns, *off, column, from, &matched))
+                .collect();
+            println!("{}", line.join("   ").trim_end());
+        }
+    }
+
+    /* Formats the bytes from..from + ROW_LEN of the window around an occurrence */
+    fn row(
+        &self,
+        columns: &[(usize, Vec<Option<u8>>)],
+        off: usize,
+        column: &[Option<u8>],
+        from: usize,
+        matched: &std::ops::Range<usize>,
+    ) -> String {
+        // The offset of the first byte of the row which is inside of the file
+        let skip = match column[from..]
+            .iter()
+            .take(ROW_LEN)
+            .position(|b| b.is_some())
+        {
+            Some(skip) => skip,
+            None => return " ".repeat(COLUMN_WIDTH),
+        };
+        let offset = off as isize - self.context as isize + (from + skip) as isize;
+
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for i in from..from + ROW_LEN {
+            let b = match column.get(i).cloned().flatten() {
+                Some(b) => b,
+                None => {
+                    hex.push_str("   ");
+                    ascii.push(' ');
+                    continue;
+                }
+            };
+            let c = if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            };
+
+            let highlight = if matched.contains(&i) {
+                Some(MATCHED)
+            } else if columns.iter().any(|(_, other)| other[i] != Some(b)) {
+                Some(DIVERGED)
+            } else {
+                None
+            };
+            match highlight {
+                Some(code) if self.color => {
+                    hex.push_str(&format!("{}{:02x}{} ", code, b, RESET));
+                    ascii.push_str(&format!("{}{}{}", code, c, RESET));
+                }
+                _ => {
+                    hex.push_str(&format!("{:02x} ", b));
+                    ascii.push(c);
+                }
+            }
+        }
+
+        format!("{:08x}  {} |{}|", offset, hex, ascii)
+    }
+}
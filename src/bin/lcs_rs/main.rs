@@ -0,0 +1,611 @@
+use clap::{value_t, App, AppSettings, Arg, SubCommand};
+use hexdump::Hexdump;
+use lcs_rs::{read_file_and_preprocess, Corpus, Error, Query};
+use output::{Dump, Format, Printer};
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::{fs, process};
+
+mod hexdump;
+mod manifest;
+mod output;
+
+const K_DEFAULT: u32 = 2;
+
+fn main() {
+    if let Err(why) = run() {
+        eprintln!("Error: {}", why);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let app = App::new("lcs_rs")
+        .version("0.1.0")
+        .author("William Arnold <willarnold@berkeley.edu>")
+        .about("Finds the longest common byte subsequence in an arbitrary number of files")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::with_name("min-files")
+                .short("k")
+                .long("min-files")
+                .help("The minimum number of files the subsequence must be present in")
+                .default_value("2"),
+        )
+        .arg(
+            Arg::with_name("min-fraction")
+                .long("min-fraction")
+                .takes_value(true)
+                .value_name("F")
+                .help("The minimum fraction of the files, between 0 and 1, the subsequence must be present in, overriding --min-files"),
+        )
+        .arg(
+            Arg::with_name("top")
+                .short("n")
+                .long("top")
+                .takes_value(true)
+                .value_name("N")
+                .help("Report the N longest distinct common substrings instead of only the longest"),
+        )
+        .arg(
+            Arg::with_name("min-length")
+                .short("l")
+                .long("min-length")
+                .takes_value(true)
+                .value_name("L")
+                .conflicts_with("top")
+                .help("Report every maximal common substring of at least L bytes"),
+        )
+        .arg(
+            Arg::with_name("most-shared")
+                .long("most-shared")
+                .takes_value(true)
+                .value_name("L")
+                .conflicts_with_all(&[
+                    "top",
+                    "min-length",
+                    "min-fraction",
+                    "exact",
+                    "min-occurrences",
+                    "require",
+                    "exclude",
+                    "background",
+                    "groups",
+                    "weights",
+                ])
+                .help("Report the substring of at least L bytes present in the most files instead"),
+        )
+        .arg(
+            Arg::with_name("all-k")
+                .long("all-k")
+                .conflicts_with_all(&[
+                    "top",
+                    "min-length",
+                    "min-fraction",
+                    "most-shared",
+                    "exact",
+                    "min-occurrences",
+                    "require",
+                    "exclude",
+                    "background",
+                    "groups",
+                    "weights",
+                ])
+                .help("Report the LCS for every minimum number of files from 1 to the number of files"),
+        )
+        .arg(
+            Arg::with_name("exact")
+                .long("exact")
+                .help("Only report substrings present in exactly as many files as the minimum, listing which ones"),
+        )
+        .arg(
+            Arg::with_name("min-occurrences")
+                .long("min-occurrences")
+                .takes_value(true)
+                .value_name("M")
+                .help("Only report substrings occurring at least M times across all files"),
+        )
+        .arg(
+            Arg::with_name("require")
+                .long("require")
+                .takes_value(true)
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only report substrings present in this file, which counts towards the minimum number of files"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only report substrings which don't occur anywhere in this file"),
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .takes_value(true)
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only report substrings occurring in at most --max-background of these files, which don't count towards the minimum number of files"),
+        )
+        .arg(
+            Arg::with_name("max-background")
+                .short("m")
+                .long("max-background")
+                .takes_value(true)
+                .value_name("M")
+                .requires("background")
+                .help("The maximum number of background files a substring may occur in [default: 0]"),
+        )
+        .arg(
+            Arg::with_name("groups")
+                .short("g")
+                .long("groups")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Count files of the same group as one, reading groups from a manifest of tab-separated `FILE GROUP` lines. Files it doesn't list are groups of their own"),
+        )
+        .arg(
+            Arg::with_name("weights")
+                .short("w")
+                .long("weights")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("min-weight")
+                .help("Weigh files, reading weights from a manifest of tab-separated `FILE WEIGHT` lines. Files it doesn't list weigh 1"),
+        )
+        .arg(
+            Arg::with_name("min-weight")
+                .long("min-weight")
+                .takes_value(true)
+                .value_name("W")
+                .requires("weights")
+                .help("Only report substrings present in files whose weights sum to at least W"),
+        )
+        .arg(
+            Arg::with_name("all-occurrences")
+                .short("a")
+                .long("all-occurrences")
+                .help("Report every occurrence in each file instead of only the first"),
+        )
+        .arg(
+            Arg::with_name("full-interval")
+                .long("full-interval")
+                .requires("all-occurrences")
+                .help("Also report occurrences outside of the smallest suffix array range the LCS was found in"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "ndjson"])
+                .default_value("text")
+                .help("Print results as text, a single JSON document or one JSON object per line"),
+        )
+        .arg(
+            Arg::with_name("dump")
+                .long("dump")
+                .takes_value(true)
+                .possible_values(&["hex", "raw", "escaped"])
+                .help("Also print the common substring itself after its offsets"),
+        )
+        .arg(
+            Arg::with_name("hexdump")
+                .short("x")
+                .long("hexdump")
+                .help("Also print a side by side hexdump of the common substring in every file it was found in"),
+        )
+        .arg(
+            Arg::with_name("context")
+                .short("C")
+                .long("context")
+                .takes_value(true)
+                .value_name("BYTES")
+                .default_value("16")
+                .help("The number of bytes before and after the common substring to include in the hexdump"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help("Highlight the common substring and where the files diverge in the hexdump"),
+        )
+        .arg(
+            Arg::with_name("extract")
+                .long("extract")
+                .takes_value(true)
+                .value_name("PATH")
+                .conflicts_with_all(&["top", "min-length", "all-k"])
+                .help("Write the common substring to the given file"),
+        )
+        .arg(
+            Arg::with_name("skip-unreadable")
+                .long("skip-unreadable")
+                .global(true)
+                .help("Leave out files which can't be read instead of failing, listing each as a tab-separated `skipped <file> <reason>` line on stderr"),
+        )
+        .arg(
+            Arg::with_name("index")
+                .short("i")
+                .long("index")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Search an index saved with `lcs_rs index` instead of reading files"),
+        )
+        .arg(
+            Arg::with_name("verify-sources")
+                .long("verify-sources")
+                .requires("index")
+                .help("Reject the index if the contents of any of the files it was built from have changed, rather than only their length or modification time"),
+        )
+        .arg(
+            Arg::with_name("files")
+                .help("The files to search through")
+                .required_unless("index")
+                .conflicts_with("index")
+                .min_values(1),
+        )
+        .subcommand(
+            SubCommand::with_name("index")
+                .about("Builds the suffix array of the given files and saves it for later searches")
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true)
+                        .help("Where to write the index"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .help("The files to index")
+                        .required(true)
+                        .min_values(1),
+                ),
+        );
+    #[cfg(feature = "parallel")]
+    let app = app.arg(
+        Arg::with_name("threads")
+            .short("j")
+            .long("threads")
+            .takes_value(true)
+            .value_name("N")
+            .global(true)
+            .help("The number of threads to build the suffix and lcp arrays with, by default one per core"),
+    );
+    let matches = app.get_matches();
+
+    #[cfg(feature = "parallel")]
+    {
+        if matches.is_present("threads") {
+            let threads = value_t!(matches, "threads", usize).unwrap_or_else(|e| e.exit());
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+                .expect("Error setting up the thread pool");
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("index") {
+        let files: Vec<_> = matches.values_of("files").unwrap().collect();
+        let (files, data, _) = read_files(&files, matches.is_present("skip-unreadable"))?;
+        let corpus = Corpus::new(&files, &data)?;
+        return corpus.save(matches.value_of("output").unwrap());
+    }
+
+    let k = matches
+        .value_of("min-files")
+        .map(|k| k.parse::<u32>())
+        .unwrap_or(Ok(K_DEFAULT))
+        .unwrap();
+
+    let mut skipped = Vec::new();
+    let corpus = match matches.value_of("index") {
+        Some(path) => {
+            let corpus = Corpus::load(path)?;
+            if matches.is_present("verify-sources") {
+                corpus.verify_sources()?;
+            }
+            corpus
+        }
+        None => {
+            let mut files: Vec<_> = matches.values_of("files").unwrap().collect();
+            //Required and excluded files are searched as well, even if they weren't listed
+            for f in matches.values_of("require").into_iter().flatten() {
+                if !files.contains(&f) {
+                    files.push(f);
+                }
+            }
+            for f in matches.values_of("exclude").into_iter().flatten() {
+                if !files.contains(&f) {
+                    files.push(f);
+                }
+            }
+            for f in matches.values_of("background").into_iter().flatten() {
+                if !files.contains(&f) {
+                    files.push(f);
+                }
+            }
+            let (files, data, unread) = read_files(&files, matches.is_present("skip-unreadable"))?;
+            skipped = unread;
+            Corpus::new(&files, &data)?
+        }
+    };
+
+    let fraction = matches
+        .value_of("min-fraction")
+        .map(|_| value_t!(matches, "min-fraction", f64).unwrap_or_else(|e| e.exit()));
+    let query = match fraction {
+        Some(fraction) => Query::fraction(fraction),
+        None => Query::new(k),
+    };
+    let query = query
+        .require(find_documents(&corpus, matches.values_of("require")))
+        .exclude(find_documents(&corpus, matches.values_of("exclude")));
+    let query = if matches.is_present("exact") {
+        query.exact()
+    } else {
+        query
+    };
+    let query = match matches.value_of("min-occurrences") {
+        Some(_) => {
+            let min_occurrences =
+                value_t!(matches, "min-occurrences", usize).unwrap_or_else(|e| e.exit());
+            query.min_occurrences(min_occurrences)
+        }
+        None => query,
+    };
+    let query = match matches.value_of("groups") {
+        Some(path) => query.groups(read_groups(&corpus, path)?),
+        None => query,
+    };
+    let query = match matches.value_of("weights") {
+        Some(path) => {
+            let min_weight = value_t!(matches, "min-weight", f64).unwrap_or_else(|e| e.exit());
+            query.weights(read_weights(&corpus, path)?, min_weight)
+        }
+        None => query,
+    };
+    let query = if matches.is_present("background") {
+        let max_background = match matches.value_of("max-background") {
+            Some(_) => value_t!(matches, "max-background", usize).unwrap_or_else(|e| e.exit()),
+            None => 0,
+        };
+        query.background(
+            find_documents(&corpus, matches.values_of("background")),
+            max_background,
+        )
+    } else {
+        query
+    };
+
+    let format = Format::from_name(matches.value_of("format").unwrap());
+    let dump = matches.value_of("dump").map(Dump::from_name);
+    let hexdump = if matches.is_present("hexdump") {
+        let context = value_t!(matches, "context", usize).unwrap_or_else(|e| e.exit());
+        let color = match matches.value_of("color").unwrap() {
+            "always" => true,
+            "never" => false,
+            _ => io::stdout().is_terminal(),
+        };
+        Some(Hexdump::new(&corpus, context, color))
+    } else {
+        None
+    };
+    let mut printer = Printer::new(
+        &corpus,
+        format,
+        matches.is_present("all-occurrences"),
+        dump,
+        hexdump,
+        &skipped,
+    );
+
+    let k = corpus.resolve_k(&query)?;
+    if let Some(fraction) = fraction {
+        printer.note(&format!(
+            "Using k = {} for a fraction of {} of the files",
+            k, fraction
+        ));
+    }
+
+    if matches.is_present("min-length") {
+        let min_length = value_t!(matches, "min-length", usize).unwrap_or_else(|e| e.exit());
+
+        for res in corpus.common_substrings(query, min_length)? {
+            let heading = format!("Common substring found with length {}", res.length);
+            printer.result(&heading, k, &res);
+        }
+        printer.finish();
+        return Ok(());
+    }
+
+    if matches.is_present("most-shared") {
+        let min_length = value_t!(matches, "most-shared", usize).unwrap_or_else(|e| e.exit());
+        let res = corpus.most_shared(min_length);
+
+        let heading = format!(
+            "Substring found with length {} in {} files",
+            res.length,
+            res.occurrences.len()
+        );
+        printer.result(&heading, res.occurrences.len() as u32, &res);
+        printer.finish();
+
+        if let Some(path) = matches.value_of("extract") {
+            extract(path, &res.bytes)?;
+        }
+        return Ok(());
+    }
+
+    if matches.is_present("background") {
+        let n = match matches.value_of("top") {
+            Some(_) => value_t!(matches, "top", usize).unwrap_or_else(|e| e.exit()),
+            None => 1,
+        };
+        let results = corpus.contrast(query, n)?;
+
+        for (i, c) in results.iter().enumerate() {
+            let heading = format!(
+                "Substring #{} found with length {} in {} files and {} background files",
+                i + 1,
+                c.result.length,
+                c.support,
+                c.background_support
+            );
+            printer.contrast(&heading, k, c);
+        }
+        printer.finish();
+
+        //--extract conflicts with --top, so this is the longest result if there is one
+        if let Some(path) = matches.value_of("extract") {
+            let bytes = results.first().map_or(&[][..], |c| &c.result.bytes[..]);
+            extract(path, bytes)?;
+        }
+        return Ok(());
+    }
+
+    if matches.is_present("top") {
+        let n = value_t!(matches, "top", usize).unwrap_or_else(|e| e.exit());
+        let results = corpus.top(query, n)?;
+
+        for (i, res) in results.iter().enumerate() {
+            let heading = format!(
+                "Common substring #{} found with length {}",
+                i + 1,
+                res.length
+            );
+            printer.result(&heading, k, res);
+        }
+        printer.finish();
+        return Ok(());
+    }
+
+    //JSON results always list every occurrence in every file
+    let full_interval =
+        matches.is_present("full-interval") || matches.value_of("format").unwrap() != "text";
+
+    if matches.is_present("all-k") {
+        let results = corpus.lcs_all_k(full_interval);
+
+        for (i, res) in results.iter().enumerate() {
+            let heading = format!("LCS for k = {} found with length {}", i + 1, res.length);
+            printer.result(&heading, i as u32 + 1, res);
+        }
+        printer.finish();
+        return Ok(());
+    }
+
+    let res = corpus.lcs_occurrences(query, full_interval)?;
+
+    let heading = format!("LCS found with length {}", res.length);
+    printer.result(&heading, k, &res);
+    printer.finish();
+
+    if let Some(path) = matches.value_of("extract") {
+        extract(path, &res.bytes)?;
+    }
+
+    Ok(())
+}
+
+/* Writes a common substring to the file given with --extract */
+fn extract(path: &str, bytes: &[u8]) -> Result<(), Error> {
+    fs::write(path, bytes).map_err(|source| Error::Io {
+        path: path.into(),
+        source,
+    })
+}
+
+/* Reads the group of every document from a manifest, numbering groups from 0 */
+fn read_groups(corpus: &Corpus, path: &str) -> Result<Vec<usize>, Error> {
+    let manifest = manifest::read(path)?;
+    let mut numbers: HashMap<&str, usize> = HashMap::new();
+    let mut n_groups = 0;
+    let mut groups = Vec::with_capacity(corpus.num_documents());
+    for doc in 0..corpus.num_documents() {
+        let group = match manifest.get(corpus.name(doc)) {
+            Some(label) => *numbers.entry(label).or_insert_with(|| {
+                n_groups += 1;
+                n_groups - 1
+            }),
+            //Files missing from the manifest are groups of their own
+            None => {
+                n_groups += 1;
+                n_groups - 1
+            }
+        };
+        groups.push(group);
+    }
+    Ok(groups)
+}
+
+/* Reads the weight of every document from a manifest */
+fn read_weights(corpus: &Corpus, path: &str) -> Result<Vec<f64>, Error> {
+    let manifest = manifest::read(path)?;
+    (0..corpus.num_documents())
+        .map(|doc| match manifest.get(corpus.name(doc)) {
+            Some(weight) => weight.parse::<f64>().map_err(|_| {
+                let reason = format!("{} is not a valid weight for {}", weight, corpus.name(doc));
+                manifest::invalid(path, reason)
+            }),
+            //Files missing from the manifest weigh as much as any ordinary file
+            None => Ok(1.0),
+        })
+        .collect()
+}
+
+/* Looks up the index of every named file in the corpus, exiting if one of them wasn't searched */
+fn find_documents(corpus: &Corpus, names: Option<clap::Values>) -> Vec<usize> {
+    names
+        .into_iter()
+        .flatten()
+        .map(|name| {
+            (0..corpus.num_documents())
+                .find(|&i| corpus.name(i) == name)
+                .unwrap_or_else(|| {
+                    clap::Error::with_description(
+                        &format!("{} is not one of the files being searched", name),
+                        clap::ErrorKind::InvalidValue,
+                    )
+                    .exit()
+                })
+        })
+        .collect()
+}
+
+/* The names and contents of the files which were read, and the files which were skipped along
+ * with the reason
+ */
+type ReadFiles<'a> = (Vec<&'a str>, Vec<Vec<u16>>, Vec<(String, String)>);
+
+/* Reads every file, failing on the first one which can't be read unless skip is set, in which
+ * case it's left out along with its name, reported on stderr and returned with the reason.
+ */
+fn read_files<'a>(files: &[&'a str], skip: bool) -> Result<ReadFiles<'a>, Error> {
+    let mut read: Vec<&str> = Vec::with_capacity(files.len());
+    let mut data: Vec<Vec<u16>> = Vec::with_capacity(files.len());
+    let mut skipped = Vec::new();
+    for f in files {
+        match read_file_and_preprocess(f) {
+            Ok(bstr) => {
+                read.push(f);
+                data.push(bstr);
+            }
+            Err(Error::Io { source, .. }) if skip => {
+                eprintln!("skipped\t{}\t{}", f, source);
+                skipped.push((f.to_string(), source.to_string()));
+            }
+            Err(why) => return Err(why),
+        }
+    }
+    Ok((read, data, skipped))
+}
@@ -0,0 +1,43 @@
+use lcs_rs::Error;
+use std::collections::HashMap;
+use std::{fs, io};
+
+/* Reads a manifest assigning a value to each of a number of files, as one tab-separated
+ * `FILE VALUE` pair per line, and maps every file to its value. Empty lines and lines starting
+ * with # are skipped. If a file is listed more than once its first value is used.
+ */
+pub fn read(path: &str) -> Result<HashMap<String, String>, Error> {
+    let text = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.into(),
+        source,
+    })?;
+
+    let mut manifest = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.rsplit_once('\t') {
+            Some((file, value)) => {
+                manifest
+                    .entry(file.to_string())
+                    .or_insert_with(|| value.trim().to_string());
+            }
+            None => {
+                return Err(invalid(
+                    path,
+                    format!("line {}: expected a tab-separated FILE and value", i + 1),
+                ))
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+/* The error for a manifest which was read but whose contents don't make sense */
+pub fn invalid(path: &str, reason: String) -> Error {
+    Error::Io {
+        path: path.into(),
+        source: io::Error::new(io::ErrorKind::InvalidData, reason),
+    }
+}
@@ -0,0 +1,190 @@
+use crate::hexdump::Hexdump;
+use lcs_rs::{ComputeResult, Contrast, Corpus};
+use serde_json::{json, Value};
+use std::ascii;
+use std::io::{self, Write};
+
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Format {
+        match name {
+            "json" => Format::Json,
+            "ndjson" => Format::Ndjson,
+            _ => Format::Text,
+        }
+    }
+}
+
+/* How the common substring itself is printed in text mode */
+pub enum Dump {
+    Hex,
+    Raw,
+    Escaped,
+}
+
+impl Dump {
+    pub fn from_name(name: &str) -> Dump {
+        match name {
+            "raw" => Dump::Raw,
+            "escaped" => Dump::Escaped,
+            _ => Dump::Hex,
+        }
+    }
+}
+
+/* Prints results either as text or following the JSON schema documented in the README.
+ * JSON output is collected and printed as a single document by `finish`, while NDJSON
+ * starts with the input statistics and prints every result on its own line.
+ */
+pub struct Printer<'a> {
+    format: Format,
+    all_occurrences: bool,
+    dump: Option<Dump>,
+    hexdump: Option<Hexdump<'a>>,
+    inputs: Value,
+    results: Vec<Value>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(
+        corpus: &Corpus,
+        format: Format,
+        all_occurrences: bool,
+        dump: Option<Dump>,
+        hexdump: Option<Hexdump<'a>>,
+        skipped: &[(String, String)],
+    ) -> Printer<'a> {
+        let inputs = json!({
+            "files": corpus.num_documents(),
+            "bytes": (0..corpus.num_documents()).map(|i| corpus.document_len(i)).sum::<usize>(),
+            "skipped": skipped
+                .iter()
+                .map(|(file, reason)| json!({ "file": file, "reason": reason }))
+                .collect::<Vec<_>>(),
+        });
+
+        if let Format::Ndjson = format {
+            println!("{}", json!({ "schema": 1, "inputs": inputs }));
+        }
+
+        Printer {
+            format,
+            all_occurrences,
+            dump,
+            hexdump,
+            inputs,
+            results: Vec::new(),
+        }
+    }
+
+    /* Prints a line of text ahead of the results, which is left out of JSON output */
+    pub fn note(&self, line: &str) {
+        if let Format::Text = self.format {
+            println!("{}", line);
+        }
+    }
+
+    /* Reports a substring found in at least k files, headed by the given line in text mode */
+    pub fn result(&mut self, heading: &str, k: u32, res: &ComputeResult) {
+        if let Format::Text = self.format {
+            self.print_result(heading, res);
+            return;
+        }
+
+        let result = result_json(k, res);
+        self.emit(result);
+    }
+
+    /* Reports a substring found by a contrastive search along with its support in either group */
+    pub fn contrast(&mut self, heading: &str, k: u32, contrast: &Contrast) {
+        if let Format::Text = self.format {
+            self.print_result(heading, &contrast.result);
+            return;
+        }
+
+        let mut result = result_json(k, &contrast.result);
+        result["support"] = json!(contrast.support);
+        result["background_support"] = json!(contrast.background_support);
+        self.emit(result);
+    }
+
+    pub fn finish(self) {
+        if let Format::Json = self.format {
+            let output = json!({
+                "schema": 1,
+                "inputs": self.inputs,
+                "results": self.results,
+            });
+            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+        }
+    }
+
+    fn print_result(&self, heading: &str, res: &ComputeResult) {
+        println!("{}", heading);
+        self.print_occurrences(&res.occurrences);
+        self.print_bytes(&res.bytes);
+        if let Some(hexdump) = &self.hexdump {
+            hexdump.print(res);
+        }
+    }
+
+    fn emit(&mut self, result: Value) {
+        match self.format {
+            Format::Ndjson => println!("{}", result),
+            _ => self.results.push(result),
+        }
+    }
+
+    fn print_occurrences(&self, occurrences: &[(&str, Vec<usize>)]) {
+        for (f, offs) in occurrences {
+            if self.all_occurrences {
+                let offs: Vec<String> = offs.iter().map(|o| o.to_string()).collect();
+                println!("-> in {} at {}", f, offs.join(", "));
+            } else {
+                println!("-> in {} at {}", f, offs[0]);
+            }
+        }
+    }
+
+    fn print_bytes(&self, bytes: &[u8]) {
+        match self.dump {
+            Some(Dump::Hex) => println!("{}", hex(bytes)),
+            Some(Dump::Escaped) => {
+                let escaped: Vec<u8> = bytes
+                    .iter()
+                    .flat_map(|&b| ascii::escape_default(b))
+                    .collect();
+                println!("{}", String::from_utf8_lossy(&escaped));
+            }
+            Some(Dump::Raw) => {
+                let mut out = io::stdout();
+                out.write_all(bytes)
+                    .and_then(|_| out.write_all(b"\n"))
+                    .expect("Error writing to stdout");
+            }
+            None => {}
+        }
+    }
+}
+
+fn result_json(k: u32, res: &ComputeResult) -> Value {
+    json!({
+        "k": k,
+        "length": res.length,
+        "substring": hex(&res.bytes),
+        "files": res
+            .occurrences
+            .iter()
+            .map(|(f, offs)| json!({ "name": f, "offsets": offs }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
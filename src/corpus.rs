@@ -0,0 +1,698 @@
+use crate::lcs;
+use crate::{CommonSubstring, ComputeResult, Contrast, Error, Query};
+use bio::data_structures::suffix_array::RawSuffixArray;
+use intervals::IntervalScan;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashSet};
+use std::*;
+use store::Buffer;
+
+mod intervals;
+mod store;
+
+/* A generalized suffix array over a set of named documents. The combined text, suffix array
+ * and lcp array are built once, after which any number of queries can be answered against them.
+ */
+pub struct Corpus {
+    names: Vec<String>,
+    combined: Buffer<u16>,
+    sentinel_pos: Vec<usize>,
+    file_starts: Vec<usize>,
+    suffix_array: Buffer<usize>,
+    lcp_array: Buffer<isize>,
+    rmq: sync::OnceLock<lcs::LcpRmq>,
+}
+
+impl Corpus {
+    /* Builds the corpus from the given file names and their contents, as returned by
+     * `read_file_and_preprocess`. Every document must end with the 0 sentinel, which must not
+     * occur anywhere else in it.
+     */
+    pub fn new(files: &[&str], data: &Vec<Vec<u16>>) -> Result<Corpus, Error> {
+        if files.len() != data.len() {
+            return Err(Error::MismatchedNames {
+                names: files.len(),
+                documents: data.len(),
+            });
+        }
+        //The first sentinel of every document must be its last character
+        let terminated = |s: &[u16]| s.iter().position(|&c| c == 0).map(|i| i + 1) == Some(s.len());
+        if let Some(index) = (0..data.len()).find(|&i| !terminated(&data[i])) {
+            return Err(Error::MissingSentinel {
+                index,
+                name: files[index].to_string(),
+            });
+        }
+
+        let total_length = data.iter().map(|s| s.len()).sum();
+        let mut combined: Vec<u16> = Vec::with_capacity(total_length);
+        let mut sentinel_pos: Vec<usize> = Vec::new();
+        let mut file_starts: Vec<usize> = Vec::new();
+
+        for s in data {
+            file_starts.push(combined.len());
+            combined.extend(s);
+            let sent_ind = combined.len() - 1;
+            sentinel_pos.push(sent_ind);
+        }
+
+        let names = files.iter().map(|f| f.to_string()).collect();
+        Corpus::build(names, combined, sentinel_pos, file_starts)
+    }
+
+    /* Builds the corpus from raw documents, each given along with an identifier which is used
+     * as its name in results.
+     */
+    pub fn from_documents<I, S, D>(documents: I) -> Result<Corpus, Error>
+    where
+        I: IntoIterator<Item = (S, D)>,
+        S: Into<String>,
+        D: AsRef<[u8]>,
+    {
+        let mut names: Vec<String> = Vec::new();
+        let mut combined: Vec<u16> = Vec::new();
+        let mut sentinel_pos: Vec<usize> = Vec::new();
+        let mut file_starts: Vec<usize> = Vec::new();
+
+        for (name, doc) in documents {
+            names.push(name.into());
+            file_starts.push(combined.len());
+            combined.extend(doc.as_ref().iter().map(|&b| (b as u16) + 1));
+            sentinel_pos.push(combined.len());
+            combined.push(0);
+        }
+
+        Corpus::build(names, combined, sentinel_pos, file_starts)
+    }
+
+    fn build(
+        names: Vec<String>,
+        combined: Vec<u16>,
+        sentinel_pos: Vec<usize>,
+        file_starts: Vec<usize>,
+    ) -> Result<Corpus, Error> {
+        if sentinel_pos.is_empty() {
+            return Err(Error::NoDocuments);
+        }
+        if sentinel_pos.len() > u16::MAX as usize {
+            return Err(Error::TooManyDocuments {
+                documents: sentinel_pos.len(),
+                max: u16::MAX as usize,
+            });
+        }
+
+        let suffix_array: RawSuffixArray =
+            lcs::suffix_array_u16(&combined, &(sentinel_pos.len() as u16));
+        let lcp_array = lcs::lcp_unique_sentinels(&combined, &suffix_array);
+
+        Ok(Corpus {
+            names,
+            combined: combined.into(),
+            sentinel_pos,
+            file_starts,
+            suffix_array: suffix_array.into(),
+            lcp_array: lcp_array.into(),
+            rmq: sync::OnceLock::new(),
+        })
+    }
+
+    pub fn num_documents(&self) -> usize {
+        self.sentinel_pos.len()
+    }
+
+    pub fn name(&self, doc: usize) -> &str {
+        &self.names[doc]
+    }
+
+    /* The length in bytes of the given document */
+    pub fn document_len(&self, doc: usize) -> usize {
+        self.sentinel_pos[doc] - self.file_starts[doc]
+    }
+
+    /* Returns the original bytes of the given document at start..end, clamped to its length */
+    pub fn document_bytes(&self, doc: usize, start: usize, end: usize) -> Vec<u8> {
+        let end = cmp::min(end, self.document_len(doc));
+        let start = cmp::min(start, end);
+        self.bytes(self.file_starts[doc] + start, end - start)
+    }
+
+    /* The number of suffixes in the suffix array, including one per document sentinel */
+    pub fn num_suffixes(&self) -> usize {
+        self.suffix_array.len()
+    }
+
+    /* Returns the position in the combined text of the suffix with the given rank */
+    pub fn suffix(&self, rank: usize) -> usize {
+        self.suffix_array[rank]
+    }
+
+    /* Returns the document containing the given position of the combined text along with the
+     * offset within that document, or None if the position is a sentinel.
+     */
+    pub fn document(&self, pos: usize) -> Option<(usize, usize)> {
+        lcs::get_string_index(pos, &self.sentinel_pos).map(|si| (si, pos - self.file_starts[si]))
+    }
+
+    /* Returns the range minimum structure over the lcp array, which is built on first use */
+    pub fn rmq(&self) -> &lcs::LcpRmq {
+        self.rmq.get_or_init(|| lcs::LcpRmq::new(&self.lcp_array))
+    }
+
+    /* Returns the length of the longest common prefix of the suffixes with the given ranks */
+    pub fn lcp(&self, rank_a: usize, rank_b: usize) -> usize {
+        if rank_a == rank_b {
+            let pos = self.suffix_array[rank_a];
+            return match lcs::get_string_index(pos, &self.sentinel_pos) {
+                Some(si) => self.sentinel_pos[si] - pos,
+                None => 0,
+            };
+        }
+
+        self.rmq().lcp(rank_a, rank_b)
+    }
+
+    /* Finds the longest substring present in at least k documents, or matching the given query */
+    pub fn lcs<Q: Into<Query>>(&self, query: Q) -> Result<ComputeResult<'_>, Error> {
+        self.lcs_occurrences(query, false)
+    }
+
+    /* Same as `lcs`, but if full_interval is set the occurrences are taken from the full lcp
+     * interval of the substring rather than the smallest delta range it was found in.
+     */
+    pub fn lcs_occurrences<Q: Into<Query>>(
+        &self,
+        query: Q,
+        full_interval: bool,
+    ) -> Result<ComputeResult<'_>, Error> {
+        let query = self.prepare(query)?;
+        Ok(self.lcs_in(&self.names(), &query, full_interval))
+    }
+
+    /* Finds the longest common substring for every k from 1 to the number of documents.
+     * The result for k is at index k - 1.
+     */
+    pub fn lcs_all_k(&self, full_interval: bool) -> Vec<ComputeResult<'_>> {
+        let names = self.names();
+        (1..=self.num_documents() as u32)
+            .map(|k| self.lcs_in(&names, &Query::new(k), full_interval))
+            .collect()
+    }
+
+    /* Finds the n longest distinct common substrings present in at least k documents, or
+     * matching the given query.
+     */
+    pub fn top<Q: Into<Query>>(&self, query: Q, n: usize) -> Result<Vec<ComputeResult<'_>>, Error> {
+        let query = self.prepare(query)?;
+        Ok(self.top_in(&self.names(), &query, n))
+    }
+
+    /* Finds the n longest distinct substrings present in at least k documents outside of the
+     * query's background and in at most max_background of its background documents, along with
+     * the number of documents of either group they occur in.
+     */
+    pub fn contrast<Q: Into<Query>>(&self, query: Q, n: usize) -> Result<Vec<Contrast<'_>>, Error> {
+        let query = self.prepare(query)?;
+        let names = self.names();
+        let mut background = vec![false; self.num_documents()];
+        for &si in &query.background {
+            background[si] = true;
+        }
+
+        //Documents already counted are marked with the index of the result rather than cleared
+        let mut seen = vec![usize::MAX; self.num_documents()];
+        Ok(self
+            .top_intervals(&query, n)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (l, r, length))| {
+                let (mut support, mut background_support) = (0, 0);
+                for j in l..r {
+                    if let Some((si, _)) = self.document(self.suffix_array[j]) {
+                        if seen[si] != i {
+                            seen[si] = i;
+                            if background[si] {
+                                background_support += 1;
+                            } else {
+                                support += 1;
+                            }
+                        }
+                    }
+                }
+                Contrast {
+                    result: self.result(&names, l, r, length),
+                    support,
+                    background_support,
+                }
+            })
+            .collect())
+    }
+
+    /* Finds the substring of at least min_length bytes present in the most documents, along
+     * with all of its occurrences. Of the substrings present in equally many documents the
+     * longest one is chosen. The result is empty if no document is min_length bytes long.
+     */
+    pub fn most_shared(&self, min_length: usize) -> ComputeResult<'_> {
+        let min_length = cmp::max(min_length, 1);
+        let mut present = vec![usize::MAX; self.num_documents()];
+
+        //Every substring of min_length bytes is the prefix shared by one of the runs
+        let mut k = 0;
+        for (i, (l, r)) in lcs::lcp_runs(&self.lcp_array, self.num_documents(), min_length as isize)
+            .into_iter()
+            .enumerate()
+        {
+            if r - l == 1 {
+                let (si, offset) = self.document(self.suffix_array[l]).unwrap();
+                if self.document_len(si) - offset >= min_length {
+                    k = cmp::max(k, 1);
+                }
+                continue;
+            }
+
+            let mut support = 0;
+            for j in l..r {
+                if let Some((si, _)) = self.document(self.suffix_array[j]) {
+                    if present[si] != i {
+                        present[si] = i;
+                        support += 1;
+                    }
+                }
+            }
+            k = cmp::max(k, support);
+        }
+
+        //Substrings in more than k documents are shorter than min_length, so the longest one
+        //in k documents is at least min_length bytes long
+        match k {
+            0 => ComputeResult::new(Vec::new(), Vec::new()),
+            k => self.lcs_in(&self.names(), &Query::new(k as u32), true),
+        }
+    }
+
+    /* Iterates over all maximal substrings of at least min_length bytes present in at least
+     * k documents, or matching the given query.
+     */
+    pub fn common_substrings<Q: Into<Query>>(
+        &self,
+        query: Q,
+        min_length: usize,
+    ) -> Result<CommonSubstrings<'_, &Corpus>, Error> {
+        let query = self.prepare(query)?;
+        let names = self.names();
+        Ok(CommonSubstrings::new(self, names, &query, min_length))
+    }
+
+    pub(crate) fn check_k(&self, k: u32) -> Result<(), Error> {
+        self.check_query(&Query::new(k))
+    }
+
+    /* Resolves the k of a query given as a fraction against the documents which count towards
+     * it, or rather their groups. Queries given with a plain k are returned as is.
+     */
+    pub fn resolve_k(&self, query: &Query) -> Result<u32, Error> {
+        let fraction = match query.fraction {
+            Some(fraction) => fraction,
+            None => return Ok(query.k),
+        };
+        if fraction <= 0.0 || fraction > 1.0 || fraction.is_nan() {
+            return Err(Error::InvalidFraction { fraction });
+        }
+        self.check_documents(query)?;
+
+        //Leave some room for rounding errors, so that 0.6 of 5 documents is 3
+        let k = (fraction * self.counted_groups(query) as f64 - 1e-9).ceil();
+        Ok(cmp::max(k as u32, 1))
+    }
+
+    fn prepare<Q: Into<Query>>(&self, query: Q) -> Result<Query, Error> {
+        let mut query = query.into();
+        query.k = self.resolve_k(&query)?;
+        self.check_query(&query)?;
+        Ok(query)
+    }
+
+    pub(crate) fn check_query(&self, query: &Query) -> Result<(), Error> {
+        if query.k == 0 {
+            return Err(Error::ZeroK);
+        }
+        self.check_documents(query)?;
+
+        let documents = self.counted_groups(query);
+        if documents < query.k as usize {
+            return Err(Error::NotEnoughDocuments {
+                k: query.k,
+                documents,
+            });
+        }
+        Ok(())
+    }
+
+    /* Checks the documents a query constrains, groups or weighs */
+    fn check_documents(&self, query: &Query) -> Result<(), Error> {
+        let documents = self.num_documents();
+        let constrained: Vec<usize> = query
+            .required
+            .iter()
+            .chain(&query.uncounted())
+            .cloned()
+            .collect();
+        if let Some(&index) = constrained.iter().find(|&&i| i >= documents) {
+            return Err(Error::UnknownDocument { index, documents });
+        }
+        //Every set of documents is deduplicated, so a repeat means a document is in two of them
+        let mut seen = vec![false; documents];
+        for &index in &constrained {
+            if seen[index] {
+                return Err(Error::ConflictingConstraints {
+                    document: self.names[index].clone(),
+                });
+            }
+            seen[index] = true;
+        }
+        if let Some(groups) = &query.groups {
+            if groups.len() != documents {
+                return Err(Error::MismatchedGroups {
+                    groups: groups.len(),
+                    documents,
+                });
+            }
+        }
+        if let Some(weights) = &query.weights {
+            if weights.len() != documents {
+                return Err(Error::MismatchedWeights {
+                    weights: weights.len(),
+                    documents,
+                });
+            }
+            if let Some(i) = (0..documents).find(|&i| weights[i] < 0.0 || weights[i].is_nan()) {
+                return Err(Error::InvalidWeight {
+                    document: self.names[i].clone(),
+                    weight: weights[i],
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /* Counts the documents which count towards k, or rather their groups */
+    fn counted_groups(&self, query: &Query) -> usize {
+        let documents = self.num_documents();
+        let groups = query.group_of(documents);
+        let uncounted = query.uncounted();
+        let counted: HashSet<usize> = (0..documents)
+            .filter(|i| !uncounted.contains(i))
+            .map(|i| groups[i])
+            .collect();
+        counted.len()
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.names.iter().map(AsRef::as_ref).collect()
+    }
+
+    /* Returns the original bytes of the text at pos..pos + length */
+    fn bytes(&self, pos: usize, length: usize) -> Vec<u8> {
+        self.combined[pos..pos + length]
+            .iter()
+            .map(|&c| (c - 1) as u8)
+            .collect()
+    }
+
+    /* Builds the result for the substring of the given length shared by SA[delta_l..delta_r] */
+    fn result<'a>(
+        &self,
+        names: &[&'a str],
+        delta_l: usize,
+        delta_r: usize,
+        length: usize,
+    ) -> ComputeResult<'a> {
+        let mut offsets = vec![Vec::new(); self.num_documents()];
+
+        for i in delta_l..delta_r {
+            let suff_ind = self.suffix_array[i];
+            if let Some(si) = lcs::get_string_index(suff_ind, &self.sentinel_pos) {
+                offsets[si].push(suff_ind - self.file_starts[si]);
+            }
+        }
+
+        let occurrences: Vec<(&str, Vec<usize>)> = offsets
+            .into_iter()
+            .enumerate()
+            .filter(|(_, o)| !o.is_empty())
+            .map(|(i, mut o)| {
+                o.sort();
+                (names[i], o)
+            })
+            .collect();
+
+        ComputeResult::new(occurrences, self.bytes(self.suffix_array[delta_l], length))
+    }
+
+    pub(crate) fn lcs_in<'a>(
+        &self,
+        names: &[&'a str],
+        query: &Query,
+        full_interval: bool,
+    ) -> ComputeResult<'a> {
+        let (l, r, length) = if !query.checks_intervals() {
+            let (delta_ls, delta_rs, min_lcps) = self.deltas(query);
+            match lcs::max_min_lcp(&min_lcps) {
+                (_, 0) => (0, 0, 0),
+                (maxi, maxv) => (delta_ls[maxi], delta_rs[maxi], maxv as usize),
+            }
+        } else {
+            match self.top_intervals(query, 1).first() {
+                Some(&(l, r, length)) if full_interval => return self.result(names, l, r, length),
+                Some(&(l, r, length)) => self.delta_range_within(query, l, r, length),
+                None => (0, 0, 0),
+            }
+        };
+        if length == 0 {
+            //No substring matches the query
+            return ComputeResult::new(Vec::new(), Vec::new());
+        }
+
+        let (l, r) = if full_interval {
+            lcs::lcp_interval(&self.lcp_array, l, r, length as isize)
+        } else {
+            (l, r)
+        };
+
+        self.result(names, l, r, length)
+    }
+
+    /* Finds the first delta range within the lcp interval SA[l..r] sharing a prefix of exactly
+     * the given length, which is where the substring would have been found without checking
+     * the whole interval. There is one unless a longer substring in the interval matches the
+     * query, in which case the whole interval is returned.
+     */
+    fn delta_range_within(
+        &self,
+        query: &Query,
+        l: usize,
+        r: usize,
+        length: usize,
+    ) -> (usize, usize, usize) {
+        let (delta_ls, delta_rs, min_lcps) = self.deltas(query);
+        (0..min_lcps.len())
+            .find(|&j| min_lcps[j] == length as isize && l <= delta_ls[j] && delta_rs[j] <= r)
+            .map_or((l, r, length), |j| (delta_ls[j], delta_rs[j], length))
+    }
+
+    pub(crate) fn top_in<'a>(
+        &self,
+        names: &[&'a str],
+        query: &Query,
+        n: usize,
+    ) -> Vec<ComputeResult<'a>> {
+        self.top_intervals(query, n)
+            .into_iter()
+            .map(|(l, r, length)| self.result(names, l, r, length))
+            .collect()
+    }
+
+    /* Returns the full lcp intervals of the n longest distinct substrings matching the query as
+     * (l, r, length). A substring is left out of the top n if every one of its occurrences lies
+     * within an occurrence of one already found. A prefix of a substring already found is kept
+     * if it occurs anywhere else.
+     *
+     * Every lcp interval is a candidate, and they're checked longest first, so the intervals
+     * nested in a candidate have been checked before it. Whether they were found or left out,
+     * their occurrences lie within ones already found, and so do the occurrences of the
+     * candidate they are a prefix of, so only the suffixes of the candidate outside of them are
+     * looked at, and every suffix is looked at once. The occurrences found are kept by start
+     * without any one of them lying within another, so their ends increase with their starts,
+     * and the only one an occurrence could lie within is the last one starting at or before it.
+     */
+    fn top_intervals(&self, query: &Query, n: usize) -> Vec<(usize, usize, usize)> {
+        let mut candidates = Vec::new();
+        let mut intervals = IntervalScan::new(query, self.num_documents());
+        while let Some(interval) = intervals.next(self) {
+            if interval.presence.matches(query) {
+                candidates.push((interval.lb, interval.rb, interval.length));
+            }
+        }
+        candidates.sort_unstable_by_key(|&(l, _, length)| (cmp::Reverse(length), l));
+
+        let within = |found: &BTreeMap<usize, usize>, start: usize, end: usize| {
+            found
+                .range(..=start)
+                .next_back()
+                .is_some_and(|(_, &e)| end <= e)
+        };
+
+        //The candidates checked so far which aren't nested in another one checked, by l
+        let mut checked: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut found: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut unchecked = Vec::new();
+        let mut results = Vec::with_capacity(n);
+        for (l, r, length) in candidates {
+            if results.len() >= n {
+                break;
+            }
+
+            unchecked.clear();
+            let mut j = l;
+            while j < r {
+                match checked.remove(&j) {
+                    Some(end) => j = end,
+                    None => {
+                        unchecked.push(self.suffix_array[j]);
+                        j += 1;
+                    }
+                }
+            }
+            checked.insert(l, r);
+
+            if unchecked.iter().all(|&p| within(&found, p, p + length)) {
+                continue;
+            }
+            for &p in &unchecked {
+                let end = p + length;
+                if within(&found, p, end) {
+                    continue;
+                }
+                while let Some((&start, &e)) = found.range(p..).next() {
+                    if e > end {
+                        break;
+                    }
+                    found.remove(&start);
+                }
+                found.insert(p, end);
+            }
+
+            results.push((l, r, length));
+        }
+
+        results
+    }
+    /* Computes the K-good delta ranges of the suffix array matching the query, along with the
+     * length of the longest prefix shared by each one, which is 0 if it can't be reported.
+     */
+    fn deltas(&self, query: &Query) -> (Vec<usize>, Vec<usize>, Vec<isize>) {
+        let n_strings = self.num_documents();
+        let groups = query.group_of(n_strings);
+        let l0 = lcs::get_l0(
+            &self.combined,
+            &self.suffix_array,
+            &query.k,
+            &self.sentinel_pos,
+            &groups,
+        );
+        let uncounted = query.uncounted();
+        let mut support = lcs::Support::new(query.k, &groups, &query.required, &uncounted)
+            .min_occurrences(query.min_occurrences);
+        if let Some(weights) = &query.weights {
+            support = support.weighted(weights, query.min_weight);
+        }
+        let (delta_ls, delta_rs) = lcs::compute_deltas(
+            &n_strings,
+            &l0,
+            support,
+            &self.suffix_array,
+            &self.sentinel_pos,
+        );
+        let mut min_lcps = lcs::min_lcps(&delta_ls, &delta_rs, &self.lcp_array);
+
+        //A range of a single suffix shares all of it, up to the end of its document
+        for (j, (&l, &r)) in delta_ls.iter().zip(&delta_rs).enumerate() {
+            if r - l == 1 {
+                let pos = self.suffix_array[l];
+                let (si, _) = self.document(pos).unwrap();
+                min_lcps[j] = (self.sentinel_pos[si] - pos) as isize;
+            }
+        }
+
+        if !query.excluded.is_empty() {
+            lcs::exclude_deltas(
+                &mut min_lcps,
+                &delta_ls,
+                &delta_rs,
+                &self.lcp_array,
+                &self.suffix_array,
+                &self.sentinel_pos,
+                &query.excluded,
+            );
+        }
+
+        (delta_ls, delta_rs, min_lcps)
+    }
+}
+
+/* Iterates over all maximal substrings of at least min_length bytes present in at least k
+ * documents. Every lcp interval of the suffix array is visited once, bottom-up, and its prefix
+ * is reported along with every one of its occurrences if they match the query, and if no
+ * longer substring containing it occurs in the same documents.
+ */
+pub struct CommonSubstrings<'a, C: Borrow<Corpus> = Corpus> {
+    corpus: C,
+    names: Vec<&'a str>,
+    query: Query,
+    min_length: usize,
+    intervals: IntervalScan,
+}
+
+impl<'a, C: Borrow<Corpus>> CommonSubstrings<'a, C> {
+    pub(crate) fn new(corpus: C, names: Vec<&'a str>, query: &Query, min_length: usize) -> Self {
+        let intervals = IntervalScan::new(query, corpus.borrow().num_documents());
+
+        CommonSubstrings {
+            corpus,
+            names,
+            query: query.clone(),
+            min_length: cmp::max(min_length, 1),
+            intervals,
+        }
+    }
+}
+
+impl<'a, C: Borrow<Corpus>> Iterator for CommonSubstrings<'a, C> {
+    type Item = CommonSubstring<'a>;
+
+    fn next(&mut self) -> Option<CommonSubstring<'a>> {
+        let corpus = self.corpus.borrow();
+        while let Some(interval) = self.intervals.next(corpus) {
+            let presence = &interval.presence;
+            if interval.length < self.min_length || !presence.matches(&self.query) {
+                continue;
+            }
+            //Extending the prefix to the right must lose a document, which is cheap to check
+            //before checking both sides
+            if interval.max_child_documents >= presence.documents + presence.background
+                || !lcs::is_maximal(
+                    &corpus.combined,
+                    &corpus.suffix_array,
+                    &corpus.sentinel_pos,
+                    interval.lb,
+                    interval.rb,
+                    interval.length,
+                )
+            {
+                continue;
+            }
+
+            return Some(corpus.result(&self.names, interval.lb, interval.rb, interval.length));
+        }
+        None
+    }
+}
@@ -0,0 +1,258 @@
+use super::Corpus;
+use crate::{lcs, Query};
+use std::collections::VecDeque;
+use std::*;
+
+/* Where a substring occurs: how often, in how many documents outside of and within the
+ * background of a query, how often in excluded documents, and in how many groups, required
+ * documents and how much weight of the documents which count towards k.
+ */
+#[derive(Clone, Default)]
+pub struct Presence {
+    pub occurrences: usize,
+    pub documents: usize,
+    pub background: usize,
+    pub excluded: usize,
+    pub groups: usize,
+    pub required: usize,
+    pub weight: f64,
+}
+
+impl Presence {
+    fn add(&mut self, other: &Presence) {
+        self.occurrences += other.occurrences;
+        self.documents += other.documents;
+        self.background += other.background;
+        self.excluded += other.excluded;
+        self.groups += other.groups;
+        self.required += other.required;
+        self.weight += other.weight;
+    }
+
+    /* Checks every condition of the query against all occurrences of a substring */
+    pub fn matches(&self, query: &Query) -> bool {
+        let k = query.k as usize;
+        self.excluded == 0
+            && self.groups >= k
+            && (!query.exact || self.groups == k)
+            && self.required == query.required.len()
+            && self.occurrences >= query.min_occurrences
+            && self.background <= query.max_background
+            && (query.weights.is_none() || self.weight + lcs::WEIGHT_EPSILON >= query.min_weight)
+    }
+}
+
+/* An lcp interval: the suffixes in SA[lb..rb], which are all suffixes starting with a prefix
+ * of the given length, and which don't all continue with the same character. A single suffix
+ * is an interval of its own if no other suffix shares all of it.
+ */
+pub struct Interval {
+    pub lb: usize,
+    pub rb: usize,
+    pub length: usize,
+    pub presence: Presence,
+    /* The most documents the prefix occurs in when extended by any one character */
+    pub max_child_documents: usize,
+}
+
+/* An interval whose last suffix hasn't been reached yet */
+struct Open {
+    lb: usize,
+    length: isize,
+    presence: Presence,
+    max_child_documents: usize,
+}
+
+impl Open {
+    fn new(lb: usize, length: isize) -> Open {
+        Open {
+            lb,
+            length,
+            presence: Presence::default(),
+            max_child_documents: 0,
+        }
+    }
+
+    /* Accounts for a nested interval or a single suffix right within this one */
+    fn add(&mut self, child: &Presence) {
+        self.presence.add(child);
+        self.max_child_documents =
+            cmp::max(self.max_child_documents, child.documents + child.background);
+    }
+}
+
+/* Enumerates the lcp intervals of a corpus bottom-up with a single pass over the lcp array,
+ * keeping the intervals which are still open on a stack, and reports each one as soon as its
+ * last suffix has been seen along with where its prefix occurs according to a query.
+ *
+ * The documents an interval spans are counted as its suffixes, less one for every suffix of a
+ * document with another suffix earlier in the interval. The deepest interval spanning such a
+ * pair of suffixes is on the stack when the second one is reached, so the document is taken
+ * off there, and the counts of every interval are carried over to the one containing it.
+ * Groups are counted the same way. Finding the interval is a binary search of the stack, so
+ * this takes O(n log n) time in total.
+ */
+pub struct IntervalScan {
+    groups: Vec<usize>,
+    background: Vec<bool>,
+    excluded: Vec<bool>,
+    required: Vec<bool>,
+    weights: Option<Vec<f64>>,
+    //The rank of the last suffix seen of every document and group
+    last_of_document: Vec<Option<usize>>,
+    last_of_group: Vec<Option<usize>>,
+    stack: Vec<Open>,
+    next: usize,
+    pending: VecDeque<Interval>,
+}
+
+impl IntervalScan {
+    pub fn new(query: &Query, documents: usize) -> IntervalScan {
+        let groups = query.group_of(documents);
+        let n_groups = groups.iter().max().map_or(0, |&g| g + 1);
+        let mask = |indices: &[usize]| {
+            let mut mask = vec![false; documents];
+            for &si in indices {
+                mask[si] = true;
+            }
+            mask
+        };
+
+        IntervalScan {
+            background: mask(&query.background),
+            excluded: mask(&query.excluded),
+            required: mask(&query.required),
+            weights: query.weights.clone(),
+            groups,
+            last_of_document: vec![None; documents],
+            last_of_group: vec![None; n_groups],
+            //The whole suffix array shares the empty prefix
+            stack: vec![Open::new(0, 0)],
+            next: 1,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /* Returns the next interval, every one of which comes after all intervals nested in it */
+    pub fn next(&mut self, corpus: &Corpus) -> Option<Interval> {
+        loop {
+            if let Some(interval) = self.pending.pop_front() {
+                return Some(interval);
+            }
+            if self.next > corpus.num_suffixes() {
+                return None;
+            }
+            self.step(corpus);
+        }
+    }
+
+    /* Closes every interval ending with the suffix at rank next - 1, and opens the one shared
+     * by it and the suffix at rank next if that's deeper.
+     */
+    fn step(&mut self, corpus: &Corpus) {
+        let n = corpus.num_suffixes();
+        let i = self.next;
+        self.next += 1;
+        let lcp = if i < n { corpus.lcp_array[i] } else { -1 };
+
+        let mut carried = Presence::default();
+        if let Some((si, offset)) = corpus.document(corpus.suffix_array[i - 1]) {
+            carried = self.suffix(si);
+            let length = corpus.document_len(si) - offset;
+            if length as isize > cmp::max(corpus.lcp_array[i - 1], lcp) {
+                self.pending.push_back(Interval {
+                    lb: i - 1,
+                    rb: i,
+                    length,
+                    presence: carried.clone(),
+                    max_child_documents: 0,
+                });
+            }
+        }
+
+        let mut lb = i - 1;
+        while self.stack.last().is_some_and(|open| lcp < open.length) {
+            let mut open = self.stack.pop().unwrap();
+            open.add(&carried);
+            if open.length > 0 {
+                self.pending.push_back(Interval {
+                    lb: open.lb,
+                    rb: i,
+                    length: open.length as usize,
+                    presence: open.presence.clone(),
+                    max_child_documents: open.max_child_documents,
+                });
+            }
+            carried = open.presence;
+            lb = open.lb;
+        }
+        match self.stack.last_mut() {
+            Some(open) if open.length == lcp => open.add(&carried),
+            Some(_) => {
+                let mut open = Open::new(lb, lcp);
+                open.add(&carried);
+                self.stack.push(open);
+            }
+            //Every suffix has been seen
+            None => return,
+        }
+
+        if let Some((si, _)) = corpus.document(corpus.suffix_array[i]) {
+            self.deduplicate(si, i);
+        }
+    }
+
+    /* Where a single suffix of the given document occurs */
+    fn suffix(&self, si: usize) -> Presence {
+        let counted = !self.background[si] && !self.excluded[si];
+        Presence {
+            occurrences: 1,
+            documents: !self.background[si] as usize,
+            background: self.background[si] as usize,
+            excluded: self.excluded[si] as usize,
+            groups: counted as usize,
+            required: self.required[si] as usize,
+            weight: match &self.weights {
+                Some(weights) if counted => weights[si],
+                _ => 0.0,
+            },
+        }
+    }
+
+    /* Takes the document of the suffix at rank i, and its group, off the deepest interval
+     * which also spans the previous suffix of the same document, or group
+     */
+    fn deduplicate(&mut self, si: usize, i: usize) {
+        let counted = !self.background[si] && !self.excluded[si];
+        if let Some(previous) = self.last_of_document[si].replace(i) {
+            let open = self.spanning(previous);
+            let presence = &mut self.stack[open].presence;
+            if self.background[si] {
+                presence.background -= 1;
+            } else {
+                presence.documents -= 1;
+            }
+            if self.required[si] {
+                presence.required -= 1;
+            }
+            if let (Some(weights), true) = (&self.weights, counted) {
+                presence.weight -= weights[si];
+            }
+        }
+
+        if counted {
+            let g = self.groups[si];
+            if let Some(previous) = self.last_of_group[g].replace(i) {
+                let open = self.spanning(previous);
+                self.stack[open].presence.groups -= 1;
+            }
+        }
+    }
+
+    /* Finds the deepest open interval containing the suffix at the given rank, which is the
+     * last one on the stack starting at or before it
+     */
+    fn spanning(&self, rank: usize) -> usize {
+        self.stack.partition_point(|open| open.lb <= rank) - 1
+    }
+}
@@ -0,0 +1,389 @@
+use super::Corpus;
+use crate::Error;
+use crc32fast::Hasher;
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+use std::*;
+
+/* On-disk index layout, all integers little-endian and every section 8-byte aligned:
+ *
+ * header     magic, version, document count, text length, names length, checksum
+ * documents  (file start, sentinel position, source modification time) per document
+ * names      (length, utf-8 bytes) per document
+ * text       the combined u16 text
+ * suffixes   the suffix array as u64
+ * lcp        the lcp array as i64
+ *
+ * The checksum is the crc32 of everything following the header, followed by the header up
+ * to the checksum itself. The modification time of the file a document was read from is
+ * stored as seconds and nanoseconds since the epoch, followed by 1, or as zeros if the
+ * document wasn't read from a file.
+ */
+const MAGIC: &[u8; 8] = b"LCSRSIDX";
+const VERSION: u32 = 3;
+const HEADER_LEN: usize = 48;
+const CHECKSUM_AT: usize = 40;
+const DOCUMENT_LEN: usize = 32;
+
+/* An array which is either owned or lives inside a memory-mapped index file */
+pub enum Buffer<T> {
+    Owned(Vec<T>),
+    Mapped {
+        map: Arc<Mmap>,
+        offset: usize,
+        len: usize,
+        _marker: PhantomData<T>,
+    },
+}
+
+impl<T> Deref for Buffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Buffer::Owned(v) => v,
+            // Offsets are checked to be aligned and in bounds when the index is loaded
+            Buffer::Mapped {
+                map, offset, len, ..
+            } => unsafe { slice::from_raw_parts(map.as_ptr().add(*offset) as *const T, *len) },
+        }
+    }
+}
+
+impl<T> Buffer<T> {
+    fn mapped(map: &Arc<Mmap>, offset: usize, len: usize) -> Buffer<T> {
+        Buffer::Mapped {
+            map: map.clone(),
+            offset,
+            len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Buffer<T> {
+    fn from(v: Vec<T>) -> Buffer<T> {
+        Buffer::Owned(v)
+    }
+}
+
+fn invalid(path: &Path, reason: String) -> Error {
+    Error::InvalidIndex {
+        path: path.to_path_buf(),
+        reason,
+    }
+}
+
+fn check_platform() -> Result<(), Error> {
+    if cfg!(all(target_endian = "little", target_pointer_width = "64")) {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedPlatform)
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (8 - len % 8) % 8
+}
+
+fn as_bytes<T>(v: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(v.as_ptr() as *const u8, mem::size_of_val(v)) }
+}
+
+/* Writes the sections following the header, keeping track of their checksum */
+struct SectionWriter<W: Write> {
+    out: W,
+    hasher: Hasher,
+    len: usize,
+}
+
+impl<W: Write> SectionWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.hasher.update(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn pad(&mut self) -> io::Result<()> {
+        let zeros = [0u8; 8];
+        self.write(&zeros[..padding(self.len)])
+    }
+}
+
+/* Returns the modification time of the file at path if its length is the given one, as seconds
+ * and nanoseconds since the epoch
+ */
+fn modified(path: &str, len: usize) -> Option<(u64, u32)> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() != len as u64 {
+        return None;
+    }
+    let since_epoch = metadata
+        .modified()
+        .ok()?
+        .duration_since(time::UNIX_EPOCH)
+        .ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    let mut b = [0u8; 4];
+    b.copy_from_slice(&bytes[at..at + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(b)
+}
+
+impl Corpus {
+    /* Writes the corpus to an index file which can be loaded back with `Corpus::load`
+     * without rebuilding the suffix array.
+     */
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        check_platform()?;
+
+        let path = path.as_ref();
+        self.write_index(path).map_err(|e| Error::io(path, e))
+    }
+
+    fn write_index(&self, path: &Path) -> io::Result<()> {
+        let names_len: usize = self.names.iter().map(|n| 8 + n.len()).sum();
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&[0u8; HEADER_LEN])?;
+
+        let mut w = SectionWriter {
+            out,
+            hasher: Hasher::new(),
+            len: 0,
+        };
+        for (i, (&start, &end)) in self.file_starts.iter().zip(&self.sentinel_pos).enumerate() {
+            w.write(&(start as u64).to_le_bytes())?;
+            w.write(&(end as u64).to_le_bytes())?;
+            let (secs, nanos, recorded) = match modified(&self.names[i], end - start) {
+                Some((secs, nanos)) => (secs, nanos, 1u32),
+                None => (0, 0, 0),
+            };
+            w.write(&secs.to_le_bytes())?;
+            w.write(&nanos.to_le_bytes())?;
+            w.write(&recorded.to_le_bytes())?;
+        }
+        for name in &self.names {
+            w.write(&(name.len() as u64).to_le_bytes())?;
+            w.write(name.as_bytes())?;
+        }
+        w.pad()?;
+        w.write(as_bytes(&self.combined))?;
+        w.pad()?;
+        w.write(as_bytes(&self.suffix_array))?;
+        w.write(as_bytes(&self.lcp_array))?;
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&(self.num_documents() as u64).to_le_bytes());
+        header.extend_from_slice(&(self.combined.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(names_len as u64).to_le_bytes());
+        w.hasher.update(&header);
+        header.extend_from_slice(&w.hasher.finalize().to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut out = w.out;
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&header)?;
+        out.flush()
+    }
+
+    /* Memory-maps an index file written by `Corpus::save`. The index is rejected if it was
+     * written by an incompatible version or if its checksum doesn't match. The file must not
+     * be modified while the corpus is alive.
+     *
+     * It's also rejected if any of the files the documents were read from has since changed
+     * length or modification time, which only takes a look at their metadata. Files which no
+     * longer exist are ignored, see `verify_sources` to compare their contents instead.
+     */
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Corpus, Error> {
+        check_platform()?;
+
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let map = unsafe { Mmap::map(&file).map_err(|e| Error::io(path, e))? };
+
+        if map.len() < HEADER_LEN || &map[0..8] != MAGIC {
+            return Err(invalid(path, "not an lcs_rs index".to_string()));
+        }
+        let version = read_u32(&map, 8);
+        if version != VERSION {
+            return Err(invalid(
+                path,
+                format!(
+                    "format version {} is not supported (expected {}), rebuild it with `lcs_rs index`",
+                    version, VERSION
+                ),
+            ));
+        }
+        let n_docs = read_u64(&map, 16) as usize;
+        let text_len = read_u64(&map, 24) as usize;
+        let names_len = read_u64(&map, 32) as usize;
+        let checksum = read_u32(&map, CHECKSUM_AT);
+
+        //Sizes are checked before the checksum, so they could overflow
+        let overflow = || {
+            invalid(
+                path,
+                "section sizes overflow, the file is corrupted".to_string(),
+            )
+        };
+        let docs_at = HEADER_LEN;
+        let names_at = n_docs
+            .checked_mul(DOCUMENT_LEN)
+            .and_then(|len| len.checked_add(docs_at))
+            .ok_or_else(overflow)?;
+        let text_at = names_len
+            .checked_add(padding(names_len))
+            .and_then(|len| len.checked_add(names_at))
+            .ok_or_else(overflow)?;
+        let sa_at = text_len
+            .checked_mul(2)
+            .and_then(|len| len.checked_add(padding(len)))
+            .and_then(|len| len.checked_add(text_at))
+            .ok_or_else(overflow)?;
+        let lcp_at = text_len
+            .checked_mul(8)
+            .and_then(|len| len.checked_add(sa_at))
+            .ok_or_else(overflow)?;
+        let end = text_len
+            .checked_add(1)
+            .and_then(|len| len.checked_mul(8))
+            .and_then(|len| len.checked_add(lcp_at))
+            .ok_or_else(overflow)?;
+        if map.len() != end {
+            return Err(invalid(
+                path,
+                format!(
+                    "expected {} bytes but found {}, the file is truncated or corrupted",
+                    end,
+                    map.len()
+                ),
+            ));
+        }
+
+        let mut hasher = Hasher::new();
+        hasher.update(&map[HEADER_LEN..]);
+        hasher.update(&map[..CHECKSUM_AT]);
+        if hasher.finalize() != checksum {
+            return Err(invalid(
+                path,
+                "checksum mismatch, the file is corrupted".to_string(),
+            ));
+        }
+
+        //Documents must follow each other, each one terminated by a sentinel
+        let mut file_starts = Vec::with_capacity(n_docs);
+        let mut sentinel_pos = Vec::with_capacity(n_docs);
+        let mut sources = Vec::with_capacity(n_docs);
+        let mut next_start = 0;
+        for i in 0..n_docs {
+            let at = docs_at + i * DOCUMENT_LEN;
+            let start = read_u64(&map, at) as usize;
+            let sentinel = read_u64(&map, at + 8) as usize;
+            if start != next_start || sentinel < start || sentinel >= text_len {
+                return Err(invalid(path, format!("document {} is out of bounds", i)));
+            }
+            next_start = sentinel + 1;
+            file_starts.push(start);
+            sentinel_pos.push(sentinel);
+            let recorded = read_u32(&map, at + 28) == 1;
+            sources.push(if recorded {
+                Some((read_u64(&map, at + 16), read_u32(&map, at + 24)))
+            } else {
+                None
+            });
+        }
+        if next_start != text_len {
+            return Err(invalid(
+                path,
+                "the documents don't cover the text".to_string(),
+            ));
+        }
+
+        let mut names = Vec::with_capacity(n_docs);
+        let mut at = names_at;
+        let names_end = names_at + names_len;
+        for _ in 0..n_docs {
+            if names_end - at < 8 {
+                return Err(invalid(
+                    path,
+                    "document names are out of bounds".to_string(),
+                ));
+            }
+            let len = read_u64(&map, at) as usize;
+            if len > names_end - at - 8 {
+                return Err(invalid(
+                    path,
+                    "document names are out of bounds".to_string(),
+                ));
+            }
+            let name = str::from_utf8(&map[at + 8..at + 8 + len])
+                .map_err(|_| invalid(path, "document name is not valid utf-8".to_string()))?;
+            names.push(name.to_string());
+            at += 8 + len;
+        }
+        if at != names_end {
+            return Err(invalid(
+                path,
+                "document names don't match their section".to_string(),
+            ));
+        }
+
+        for (i, name) in names.iter().enumerate() {
+            let source = match sources[i] {
+                Some(source) => source,
+                None => continue,
+            };
+            let exists = fs::metadata(name).map(|m| m.is_file()).unwrap_or(false);
+            if exists && modified(name, sentinel_pos[i] - file_starts[i]) != Some(source) {
+                return Err(Error::StaleIndex {
+                    document: name.clone(),
+                });
+            }
+        }
+
+        let map = Arc::new(map);
+        Ok(Corpus {
+            names,
+            combined: Buffer::mapped(&map, text_at, text_len),
+            sentinel_pos,
+            file_starts,
+            suffix_array: Buffer::mapped(&map, sa_at, text_len),
+            lcp_array: Buffer::mapped(&map, lcp_at, text_len + 1),
+            rmq: sync::OnceLock::new(),
+        })
+    }
+
+    /* Checks that every document still matches the file it was read from, so that an index
+     * built from files which have since changed can be rejected.
+     */
+    pub fn verify_sources(&self) -> Result<(), Error> {
+        for (i, name) in self.names.iter().enumerate() {
+            let bytes = fs::read(name).map_err(|e| Error::io(name, e))?;
+            let text = &self.combined[self.file_starts[i]..self.sentinel_pos[i]];
+            if bytes.len() != text.len() || bytes.iter().zip(text).any(|(&b, &c)| b as u16 + 1 != c)
+            {
+                return Err(Error::StaleIndex {
+                    document: name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use std::{error, fmt, io};
+
+/* Everything that can go wrong when building or querying a corpus */
+#[derive(Debug)]
+pub enum Error {
+    /* The minimum number of documents k must be at least 1 */
+    ZeroK,
+    /* The fraction of documents a query asked for isn't within (0, 1] */
+    InvalidFraction { fraction: f64 },
+    /* Fewer documents than the k a query asked for */
+    NotEnoughDocuments { k: u32, documents: usize },
+    /* A corpus needs at least one document */
+    NoDocuments,
+    /* Documents must end with the 0 sentinel, which must not occur anywhere else in them */
+    MissingSentinel { index: usize, name: String },
+    /* Every document needs its own sentinel, of which there are only so many */
+    TooManyDocuments { documents: usize, max: usize },
+    /* A query refers to a document index past the end of the corpus */
+    UnknownDocument { index: usize, documents: usize },
+    /* A query puts the same document in more than one of its required, excluded and background sets */
+    ConflictingConstraints { document: String },
+    /* A query assigns groups to a different number of documents than there are */
+    MismatchedGroups { groups: usize, documents: usize },
+    /* A query weighs a different number of documents than there are */
+    MismatchedWeights { weights: usize, documents: usize },
+    /* Document weights can't be negative */
+    InvalidWeight { document: String, weight: f64 },
+    /* The number of names given doesn't match the number of documents */
+    MismatchedNames { names: usize, documents: usize },
+    /* Reading or writing a file failed */
+    Io { path: PathBuf, source: io::Error },
+    /* An index file is corrupted, truncated or of an unsupported version */
+    InvalidIndex { path: PathBuf, reason: String },
+    /* A document no longer matches the file an index was built from */
+    StaleIndex { document: String },
+    /* Index files can only be used on 64-bit little-endian targets */
+    UnsupportedPlatform,
+}
+
+impl Error {
+    pub(crate) fn io<P: Into<PathBuf>>(path: P, source: io::Error) -> Error {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ZeroK => write!(f, "The minimum number of files must be at least 1"),
+            Error::NotEnoughDocuments { k, documents } => write!(
+                f,
+                "You must pass {} files as arguments, but only {} were given",
+                k, documents
+            ),
+            Error::InvalidFraction { fraction } => write!(
+                f,
+                "The fraction of files must be greater than 0 and at most 1, but is {}",
+                fraction
+            ),
+            Error::NoDocuments => write!(f, "You must pass at least one file"),
+            Error::MissingSentinel { name, .. } => {
+                write!(f, "{} must end with a single 0 sentinel", name)
+            }
+            Error::TooManyDocuments { documents, max } => write!(
+                f,
+                "{} files were given, but at most {} are supported",
+                documents, max
+            ),
+            Error::UnknownDocument { index, documents } => write!(
+                f,
+                "There is no document {}, only {} were given",
+                index, documents
+            ),
+            Error::ConflictingConstraints { document } => write!(
+                f,
+                "{} can only be one of required, excluded or background",
+                document
+            ),
+            Error::MismatchedGroups { groups, documents } => write!(
+                f,
+                "Groups were given for {} files, but there are {}",
+                groups, documents
+            ),
+            Error::MismatchedWeights { weights, documents } => write!(
+                f,
+                "Weights were given for {} files, but there are {}",
+                weights, documents
+            ),
+            Error::InvalidWeight { document, weight } => {
+                write!(
+                    f,
+                    "The weight of {} must be at least 0, but is {}",
+                    document, weight
+                )
+            }
+            Error::MismatchedNames { names, documents } => {
+                write!(f, "{} names were given for {} files", names, documents)
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::InvalidIndex { path, reason } => {
+                write!(f, "Invalid index {}: {}", path.display(), reason)
+            }
+            Error::StaleIndex { document } => {
+                write!(f, "{} has changed since the index was built", document)
+            }
+            Error::UnsupportedPlatform => write!(
+                f,
+                "Index files are only supported on 64-bit little-endian targets"
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
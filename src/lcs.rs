@@ -1,18 +1,21 @@
 use bio::data_structures::suffix_array::RawSuffixArray;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::*;
 
+#[cfg(feature = "parallel")]
+mod parallel;
 mod sais;
 
-/* Get the L_0 corresponding to the upper bound upon which to search for K-good strings
- *
+/* Get the L_0 corresponding to the upper bound upon which to search for K-good strings,
+ * where the input strings in the same group count as one.
  */
 pub fn get_l0(
-    text: &Vec<u16>,
-    suffix_array: &RawSuffixArray,
+    text: &[u16],
+    suffix_array: &[usize],
     k: &u32,
     sentinel_pos: &Vec<usize>,
+    groups: &[usize],
 ) -> usize {
     let mut present_strs = HashSet::<usize>::new();
     let total_len = text.len();
@@ -21,7 +24,7 @@ pub fn get_l0(
     let mut n_strings: Vec<usize> = vec![(*k as usize) + 1; total_len];
     for i in (0..total_len).rev() {
         if let Some(si) = get_string_index(suffix_array[i] as usize, &sentinel_pos) {
-            present_strs.insert(si);
+            present_strs.insert(groups[si]);
             n_strings[i] = present_strs.len()
         }
     }
@@ -34,87 +37,246 @@ pub fn get_l0(
     }
     l0 + 1
 }
+/* Decides whether a delta range is K-good, keeping count of the suffixes of every input
+ * string and every group of input strings in it. A range is K-good once K distinct groups
+ * are present, along with a total weight of at least min_weight if the strings are weighted,
+ * and every required string must be present in it. Excluded strings don't count towards K.
+ * The range must also span at least min_occurrences suffixes.
+ *
+ * The number of groups, required strings and the weight present are updated whenever a
+ * counter becomes or stops being zero, so checking a range takes constant time no matter
+ * how many input strings there are.
+ */
+pub struct Support<'a> {
+    k: u32,
+    groups: &'a [usize],
+    n_required: usize,
+    is_required: Vec<bool>,
+    excluded: Vec<bool>,
+    weights: Option<(&'a [f64], f64)>,
+    min_occurrences: usize,
+    occurrences: usize,
+    type_counters: Vec<u32>,
+    group_counters: Vec<u32>,
+    present_groups: u32,
+    present_required: usize,
+    present_weight: f64,
+}
+
+/* Weights are summed up and taken away again as the window slides, which isn't exact */
+pub(crate) const WEIGHT_EPSILON: f64 = 1e-9;
+
+impl<'a> Support<'a> {
+    /* groups holds the group of every input string, numbered from 0 */
+    pub fn new(k: u32, groups: &'a [usize], required: &[usize], excluded: &[usize]) -> Support<'a> {
+        let n_strings = groups.len();
+        let n_groups = groups.iter().max().map_or(0, |&g| g + 1);
+        let mut is_required = vec![false; n_strings];
+        for &s_ind in required {
+            is_required[s_ind] = true;
+        }
+        let mut is_excluded = vec![false; n_strings];
+        for &s_ind in excluded {
+            is_excluded[s_ind] = true;
+        }
+        Support {
+            k,
+            groups,
+            n_required: required.len(),
+            is_required,
+            excluded: is_excluded,
+            weights: None,
+            min_occurrences: 0,
+            occurrences: 0,
+            type_counters: vec![0u32; n_strings],
+            group_counters: vec![0u32; n_groups],
+            present_groups: 0,
+            present_required: 0,
+            present_weight: 0.0,
+        }
+    }
+
+    /* Weighs every input string, requiring their weights in a range to sum to min_weight.
+     * Weights must not be negative, as the delta ranges are found with a sliding window.
+     */
+    pub fn weighted(mut self, weights: &'a [f64], min_weight: f64) -> Support<'a> {
+        self.weights = Some((weights, min_weight));
+        self
+    }
+
+    /* Requires ranges to span at least min_occurrences suffixes */
+    pub fn min_occurrences(mut self, min_occurrences: usize) -> Support<'a> {
+        self.min_occurrences = min_occurrences;
+        self
+    }
+
+    fn add(&mut self, s_ind: usize) {
+        self.occurrences += 1;
+        if self.excluded[s_ind] {
+            return;
+        }
+
+        self.type_counters[s_ind] += 1;
+        if self.type_counters[s_ind] == 1 {
+            self.toggle(s_ind, true);
+        }
+        let g = self.groups[s_ind];
+        self.group_counters[g] += 1;
+        if self.group_counters[g] == 1 {
+            self.present_groups += 1;
+        }
+    }
+
+    fn remove(&mut self, s_ind: usize) {
+        self.occurrences -= 1;
+        if self.excluded[s_ind] {
+            return;
+        }
+
+        self.type_counters[s_ind] -= 1;
+        if self.type_counters[s_ind] == 0 {
+            self.toggle(s_ind, false);
+        }
+        let g = self.groups[s_ind];
+        self.group_counters[g] -= 1;
+        if self.group_counters[g] == 0 {
+            self.present_groups -= 1;
+        }
+    }
+
+    /* Accounts for an input string entering or leaving the range */
+    fn toggle(&mut self, s_ind: usize, present: bool) {
+        if self.is_required[s_ind] {
+            if present {
+                self.present_required += 1;
+            } else {
+                self.present_required -= 1;
+            }
+        }
+        if let Some((weights, _)) = self.weights {
+            if present {
+                self.present_weight += weights[s_ind];
+            } else {
+                self.present_weight -= weights[s_ind];
+            }
+        }
+    }
+
+    fn is_good(&self) -> bool {
+        self.present_groups >= self.k
+            && self.occurrences >= self.min_occurrences
+            && self.present_required == self.n_required
+            && match self.weights {
+                Some((_, min_weight)) => self.present_weight + WEIGHT_EPSILON >= min_weight,
+                None => true,
+            }
+    }
+}
+
 /* Computes ranges (called deltas) upon which the elt's of SA[delta_l..delta_r]
  * correspond to K different input strings, meaning every delta range is K-good.
+ * The scan stops early once the rest of the suffix array can't be K-good anymore.
  */
 pub fn compute_deltas(
     n_strings: &usize,
     l0: &usize,
-    k: &u32,
-    suffix_array: &RawSuffixArray,
+    mut support: Support,
+    suffix_array: &[usize],
     sentinel_pos: &Vec<usize>,
 ) -> (Vec<usize>, Vec<usize>) {
     let start_ind = *n_strings;
     let end_ind = *l0 - 1;
-    let mut delta_ls = vec![0usize; end_ind + 1 - start_ind];
-    let mut delta_rs = vec![0usize; end_ind + 1 - start_ind];
-    let mut type_counters = vec![0u32; *n_strings];
-
-    delta_ls[0] = start_ind;
-    delta_rs[0] = start_ind + 1;
-    if let Some(s_ind) = get_string_index(suffix_array[start_ind] as usize, &sentinel_pos) {
-        type_counters[s_ind] += 1;
-    }
-
-    //Advance until K-good
-    while count_nonzero(&type_counters) < *k {
-        delta_rs[0] += 1;
-        let i = delta_rs[0] - 1; //The new character that is in delta_0
-        if let Some(s_ind) = get_string_index(suffix_array[i] as usize, &sentinel_pos) {
-            type_counters[s_ind] += 1;
-        }
-    }
+    let mut delta_ls = Vec::with_capacity(end_ind + 1 - start_ind);
+    let mut delta_rs = Vec::with_capacity(end_ind + 1 - start_ind);
+    let mut delta_r = start_ind;
 
-    //delta_n+1 is now K-good, can advance
-    for (j, i) in (start_ind..(end_ind + 1)).enumerate().skip(1) {
-        delta_ls[j] = i;
-        delta_rs[j] = delta_rs[j - 1];
+    for i in start_ind..(end_ind + 1) {
         //Remove one from the type counter corresponding to the i-1-th char
-        if let Some(ls_ind) = get_string_index(suffix_array[i - 1] as usize, &sentinel_pos) {
-            type_counters[ls_ind] -= 1;
-        }
-        while count_nonzero(&type_counters) < *k {
-            let new_char_ind = delta_rs[j];
-            delta_rs[j] += 1;
-            if let Some(s_ind) =
-                get_string_index(suffix_array[new_char_ind] as usize, &sentinel_pos)
-            {
-                type_counters[s_ind] += 1;
+        if i > start_ind {
+            if let Some(ls_ind) = get_string_index(suffix_array[i - 1] as usize, &sentinel_pos) {
+                support.remove(ls_ind);
             }
         }
+        //Advance until K-good
+        while !support.is_good() {
+            if delta_r == suffix_array.len() {
+                return (delta_ls, delta_rs);
+            }
+            if let Some(s_ind) = get_string_index(suffix_array[delta_r] as usize, &sentinel_pos) {
+                support.add(s_ind);
+            }
+            delta_r += 1;
+        }
+        delta_ls.push(i);
+        delta_rs.push(delta_r);
     }
 
     (delta_ls, delta_rs)
 }
 
-/* Given delta ranges, find the delta with the largest min_lcp value where the min_lcp
- * is the minimum of the lcp array values present within a given delta.
- * This corresponds to the length of the longest substring
+/* Invalidates the delta ranges whose longest common prefix also occurs in an excluded string
+ * by setting their min_lcp to 0. Any shorter prefix occurs there as well, so none of the
+ * prefixes shared by such a range qualifies.
+ */
+pub fn exclude_deltas(
+    min_lcps: &mut [isize],
+    delta_ls: &[usize],
+    delta_rs: &[usize],
+    lcp_array: &[isize],
+    suffix_array: &[usize],
+    sentinel_pos: &[usize],
+    excluded: &[usize],
+) {
+    let n = suffix_array.len();
+    let mut is_excluded = vec![false; sentinel_pos.len()];
+    for &s_ind in excluded {
+        is_excluded[s_ind] = true;
+    }
+    let excluded_ranks: Vec<bool> = (0..n)
+        .map(|i| match get_string_index(suffix_array[i], sentinel_pos) {
+            Some(s_ind) => is_excluded[s_ind],
+            None => false,
+        })
+        .collect();
+
+    //The lcp of every suffix with the closest suffix of an excluded string before and after it
+    let mut before = vec![-1isize; n];
+    let mut prev = -1isize;
+    for i in 0..n {
+        prev = if excluded_ranks[i] {
+            isize::MAX
+        } else {
+            cmp::min(prev, lcp_array[i])
+        };
+        before[i] = prev;
+    }
+    let mut after = vec![-1isize; n];
+    let mut next = -1isize;
+    for i in (0..n).rev() {
+        next = if excluded_ranks[i] {
+            isize::MAX
+        } else {
+            cmp::min(next, lcp_array[i + 1])
+        };
+        after[i] = next;
+    }
+
+    for (j, (&l, &r)) in delta_ls.iter().zip(delta_rs).enumerate() {
+        if min_lcps[j] <= cmp::max(after[l], before[r - 1]) {
+            min_lcps[j] = 0;
+        }
+    }
+}
+
+/* Given the min_lcp values of the delta ranges, as computed by `min_lcps`, find the delta
+ * with the largest one. This corresponds to the length of the longest substring
  * Returns a tuple of the form (maxi, maxv) where maxi is the index of min-max delta and
  * maxv is the minimum lcp value in delta_maxi
  */
-pub fn max_min_lcp(
-    delta_ls: &Vec<usize>,
-    delta_rs: &Vec<usize>,
-    lcp_array: &Vec<isize>,
-) -> (usize, isize) {
-    let result = delta_ls
-        .iter()
-        .zip(delta_rs)
-        .enumerate()
-        .map(|(i, (&l, &r))| {
-            let mut min = isize::MAX;
-            for k in (l + 1)..r {
-                if let Some(v) = lcp_array.get(k) {
-                    min = if v < &min { *v } else { min }
-                }
-            }
-            (i, min)
-        });
-
+pub fn max_min_lcp(min_lcps: &[isize]) -> (usize, isize) {
     let mut maxi = 0usize;
     let mut maxv = 0isize;
-    for (i, lcp_min) in result {
+    for (i, &lcp_min) in min_lcps.iter().enumerate() {
         if lcp_min > maxv {
             maxv = lcp_min;
             maxi = i;
@@ -124,12 +286,168 @@ pub fn max_min_lcp(
     (maxi, maxv)
 }
 
+/* Computes the min_lcp value of every delta range, i.e. the length of the longest prefix
+ * shared by all suffixes in SA[delta_l..delta_r].
+ *
+ * Both ends of the delta ranges only ever move right, so the minimum is kept up to date with
+ * a deque of the positions which can still become the minimum of a later range, holding
+ * increasing lcp values. Every position is pushed and popped once, for O(n) in total.
+ */
+pub fn min_lcps(delta_ls: &[usize], delta_rs: &[usize], lcp_array: &[isize]) -> Vec<isize> {
+    let mut window: collections::VecDeque<usize> = collections::VecDeque::new();
+    let mut next = delta_ls.first().map_or(0, |&l| l + 1);
+    delta_ls
+        .iter()
+        .zip(delta_rs)
+        .map(|(&l, &r)| {
+            while next < r {
+                while window
+                    .back()
+                    .is_some_and(|&k| lcp_array[k] >= lcp_array[next])
+                {
+                    window.pop_back();
+                }
+                window.push_back(next);
+                next += 1;
+            }
+            while window.front().is_some_and(|&k| k <= l) {
+                window.pop_front();
+            }
+            window.front().map_or(isize::MAX, |&k| lcp_array[k])
+        })
+        .collect()
+}
+
+/* A sparse table over the lcp array, answering range minimum queries in O(1) after
+ * O(n log n) preprocessing. The longest common prefix of any two suffixes is the minimum of
+ * the lcp values between their ranks.
+ */
+pub struct LcpRmq {
+    //levels[j][i] is the minimum of lcp_array[i..i + 2^j]
+    levels: Vec<Vec<isize>>,
+}
+
+impl LcpRmq {
+    pub fn new(lcp_array: &[isize]) -> LcpRmq {
+        let mut levels = vec![lcp_array.to_vec()];
+        let mut width = 1;
+        while 2 * width <= lcp_array.len() {
+            let prev = levels.last().unwrap();
+            let level = (0..prev.len() - width)
+                .map(|i| cmp::min(prev[i], prev[i + width]))
+                .collect();
+            levels.push(level);
+            width *= 2;
+        }
+        LcpRmq { levels }
+    }
+
+    /* Returns the minimum of lcp_array[l..r], which must not be empty */
+    pub fn min(&self, l: usize, r: usize) -> isize {
+        assert!(l < r, "empty lcp range {}..{}", l, r);
+        let j = (usize::BITS - 1 - (r - l).leading_zeros()) as usize;
+        cmp::min(self.levels[j][l], self.levels[j][r - (1 << j)])
+    }
+
+    /* Returns the length of the longest common prefix of the suffixes with the given distinct
+     * ranks. Sentinels are unique, so it never extends past the end of a file.
+     */
+    pub fn lcp(&self, rank_a: usize, rank_b: usize) -> usize {
+        let (l, r) = (cmp::min(rank_a, rank_b), cmp::max(rank_a, rank_b));
+        cmp::max(self.min(l + 1, r + 1), 0) as usize
+    }
+}
+
+/* Widens the range SA[l..r] to the full interval of suffixes sharing a prefix of at least
+ * `length`, returned as (l, r).
+ */
+pub fn lcp_interval(lcp_array: &[isize], l: usize, r: usize, length: isize) -> (usize, usize) {
+    let mut start = l;
+    while start > 0 && lcp_array[start] >= length {
+        start -= 1;
+    }
+    let mut end = r;
+    while end + 1 < lcp_array.len() && lcp_array[end] >= length {
+        end += 1;
+    }
+    (start, end)
+}
+
+/* Splits SA[start..] into maximal ranges of suffixes which all share a prefix of at least
+ * min_length, returned as (l, r). Ranges of a single suffix are included.
+ */
+pub fn lcp_runs(lcp_array: &[isize], start: usize, min_length: isize) -> Vec<(usize, usize)> {
+    let n = lcp_array.len() - 1;
+    let mut runs = Vec::new();
+    let mut l = start;
+    while l < n {
+        let mut r = l + 1;
+        while r < n && lcp_array[r] >= min_length {
+            r += 1;
+        }
+        runs.push((l, r));
+        l = r;
+    }
+    runs
+}
+
+/* Checks whether the prefix of the given length shared by the suffixes in SA[l..r], its full
+ * lcp interval, is maximal: extending it by a single character to the left or to the right
+ * always loses one of the input strings it occurs in. Otherwise a longer substring occurs in
+ * the very same input strings, and so does every longer substring it's a part of.
+ */
+pub fn is_maximal(
+    text: &[u16],
+    suffix_array: &[usize],
+    sentinel_pos: &[usize],
+    l: usize,
+    r: usize,
+    length: usize,
+) -> bool {
+    let mut strings = HashSet::new();
+    //The input strings each character preceding or following an occurrence is found in
+    let mut preceding = HashSet::new();
+    let mut following = HashSet::new();
+    for &p in &suffix_array[l..r] {
+        let s_ind = match get_string_index(p, sentinel_pos) {
+            Some(s_ind) => s_ind,
+            None => continue,
+        };
+        strings.insert(s_ind);
+        //Sentinels can't be part of a substring
+        if p > 0 && text[p - 1] != 0 {
+            preceding.insert((text[p - 1], s_ind));
+        }
+        if text[p + length] != 0 {
+            following.insert((text[p + length], s_ind));
+        }
+    }
+
+    let extends = |found: &HashSet<(u16, usize)>| {
+        let mut counts: HashMap<u16, usize> = HashMap::new();
+        for &(c, _) in found {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+        counts.values().any(|&count| count == strings.len())
+    };
+    !extends(&preceding) && !extends(&following)
+}
+
 /* Computes the lcp array values in O(n) time, considering all sentinels as unique characters.
+ * With the parallel feature it's computed from the permuted lcp array on the rayon thread
+ * pool instead, unless the pool only has a single thread.
  */
 pub fn lcp_unique_sentinels<SA: Deref<Target = RawSuffixArray>>(
     text: &[u16],
     pos: SA,
 ) -> Vec<isize> {
+    #[cfg(feature = "parallel")]
+    {
+        if rayon::current_num_threads() > 1 {
+            return parallel::lcp_unique_sentinels(text, &pos);
+        }
+    }
+
     assert_eq!(text.len(), pos.len());
     let n = text.len();
     let sentinel = text[n - 1];
@@ -150,7 +468,7 @@ pub fn lcp_unique_sentinels<SA: Deref<Target = RawSuffixArray>>(
         while pred + l < n
             && p + l < n
             && text[p + l] == text[pred + l]
-            && text[p + 1] != sentinel
+            && text[p + l] != sentinel
             && text[pred + l] != sentinel
         {
             l += 1;
@@ -164,9 +482,17 @@ pub fn lcp_unique_sentinels<SA: Deref<Target = RawSuffixArray>>(
 }
 
 /* Construct the SuffixArray using u16 instead of u8 to allow for a unique sentinel,
- * assuming the sentinel is 0u16
+ * assuming the sentinel is 0u16. With the parallel feature it's built on the rayon thread
+ * pool instead, unless the pool only has a single thread.
  */
 pub fn suffix_array_u16(text: &[u16], n_sentinels: &u16) -> RawSuffixArray {
+    #[cfg(feature = "parallel")]
+    {
+        if rayon::current_num_threads() > 1 {
+            return parallel::suffix_array_u16(text, n_sentinels);
+        }
+    }
+
     let n = text.len();
     let alphabet = sais::Alphabet::new(text);
     let mut sais = sais::SAIS::new(n);
@@ -202,25 +528,10 @@ pub fn suffix_array_u16(text: &[u16], n_sentinels: &u16) -> RawSuffixArray {
     sais.pos
 }
 
-fn count_nonzero(vec: &Vec<u32>) -> u32 {
-    let mut c = 0;
-    for i in vec {
-        if i > &0 {
-            c += 1
-        }
-    }
-    c
-}
-
-pub fn get_string_index(pos: usize, sentinel_pos: &Vec<usize>) -> Option<usize> {
-    let mut i = 0;
-    while pos > sentinel_pos[i] {
-        i += 1;
-    }
-
-    if sentinel_pos[i] == pos {
-        None
-    } else {
-        Some(i)
-    }
+/* Returns the index of the input string containing the given position of the combined text,
+ * or None if the position is a sentinel. sentinel_pos is sorted, so this is a binary search.
+ */
+pub fn get_string_index(pos: usize, sentinel_pos: &[usize]) -> Option<usize> {
+    //The index the position would be inserted at is the input string it falls in
+    sentinel_pos.binary_search(&pos).err()
 }
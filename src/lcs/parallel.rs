@@ -0,0 +1,363 @@
+use bio::data_structures::suffix_array::RawSuffixArray;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::*;
+
+/* Constructs the suffix array with prefix doubling, spreading every round over the rayon
+ * thread pool. Suffixes are first sorted by their first few characters packed into a single
+ * integer, and then by their first 2h characters using the ranks of the suffixes sorted by
+ * their first h characters as keys. As in Larsson and Sadakane's algorithm every suffix is
+ * ranked by the position of the first suffix sharing its first h characters, and only the
+ * groups of more than one such suffix are sorted again, so a round takes as long as there are
+ * suffixes left to tell apart.
+ *
+ * The groups are sorted in parallel, a large one being sorted on every thread, and the ranks
+ * are only updated once every group has been sorted, so a round only reads the ranks of the
+ * one before. Groups are marked by flagging their first suffix rather than listed, so besides
+ * the suffix array this only takes a rank and a flag per suffix. Text made of long repeats
+ * still takes a round over most suffixes for every doubling of the length of the repeats.
+ *
+ * Sentinels are ranked the way `sais::transform_text` ranks them, the last one being the
+ * smallest, and below every other character. Since every sentinel is unique no two suffixes
+ * are equal, so the result is identical to the one of the sequential SAIS.
+ */
+pub fn suffix_array_u16(text: &[u16], n_sentinels: &u16) -> RawSuffixArray {
+    let n = text.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    //Symbols are ranked from 1, leaving 0 for positions past the end of the text, and as many
+    //of them as fit are packed into a single rank, which is at least three as there are at
+    //most 2^16 sentinels and 256 other symbols
+    let sentinel_count = *n_sentinels as usize;
+    let symbol_bits = (usize::BITS - (sentinel_count + 256).leading_zeros()) as usize;
+    let packed = usize::BITS as usize / symbol_bits;
+    let sentinels: Vec<usize> = (0..n).into_par_iter().filter(|&i| text[i] == 0).collect();
+    let symbol = |i: usize| match text.get(i) {
+        None => 0,
+        Some(0) => sentinel_count - sentinels.partition_point(|&s| s < i),
+        Some(&a) => sentinel_count + a as usize,
+    };
+    let mut rank: Vec<usize> = (0..n)
+        .into_par_iter()
+        .map(|i| (0..packed).fold(0, |key, j| (key << symbol_bits) | symbol(i + j)))
+        .collect();
+    drop(sentinels);
+
+    //Suffixes are bucketed by their first symbol, and the first round sorts every bucket by
+    //the packed symbols
+    let first = |i: usize| rank[i] >> (symbol_bits * (packed - 1));
+    let mut buckets = vec![0; (1 << symbol_bits) + 1];
+    for i in 0..n {
+        buckets[first(i) + 1] += 1;
+    }
+    for b in 1..buckets.len() {
+        buckets[b] += buckets[b - 1];
+    }
+    let heads: Vec<AtomicU8> = (0..n).into_par_iter().map(|_| AtomicU8::new(0)).collect();
+    for w in buckets.windows(2).filter(|w| w[0] < w[1]) {
+        heads[w[0]].store(FIRST, Ordering::Relaxed);
+    }
+    let mut sa = vec![0; n];
+    for i in 0..n {
+        let b = first(i);
+        sa[buckets[b]] = i;
+        buckets[b] += 1;
+    }
+    drop(buckets);
+
+    let mut h = 0;
+    while refine(&mut sa, &heads, &mut rank, h) > 0 {
+        h = if h == 0 { packed } else { 2 * h };
+    }
+    sa
+}
+
+/* Flags of the first suffix of a group as of the start of a round, and of the first suffix of
+ * a group once it's been sorted
+ */
+const FIRST: u8 = 1;
+const NEW_FIRST: u8 = 2;
+
+/* Groups of more suffixes than this are sorted on every thread */
+const PARALLEL_GROUP: usize = 1 << 16;
+
+/* Sorts every group of suffixes sharing their first h characters by the rank of the suffix h
+ * characters further, and ranks every suffix by the position of the first suffix of its new
+ * group. In the first round, where h is 0, the ranks are the packed symbols, and every suffix
+ * is ranked. Returns the number of suffixes left in groups of more than one.
+ *
+ * Suffixes sharing their first h characters can't contain a sentinel within them, as every
+ * sentinel is unique, so the suffix h characters further is never past the end of the text.
+ */
+fn refine(sa: &mut [usize], heads: &[AtomicU8], rank: &mut [usize], h: usize) -> usize {
+    let n = sa.len();
+    let chunk_len = cmp::max(1 << 12, n / (16 * rayon::current_num_threads()));
+    let chunks = n.div_ceil(chunk_len);
+    let flagged = |j: usize, flag: u8| heads[j].load(Ordering::Relaxed) & flag != 0;
+
+    //Every group is sorted by the thread handling the chunk its first suffix is in
+    let suffixes = Scatter::new(sa);
+    {
+        let rank: &[usize] = rank;
+        (0..chunks)
+            .into_par_iter()
+            .for_each_init(Vec::new, |keyed, c| {
+                for (s, e) in groups(heads, c * chunk_len, cmp::min(n, (c + 1) * chunk_len)) {
+                    if e - s > 1 {
+                        let group = unsafe { suffixes.range(s, e) };
+                        sort_group(group, &heads[s..e], keyed, |i| rank[i + h]);
+                    }
+                }
+            });
+    }
+
+    let ranks = Scatter::new(rank);
+    let left = (0..chunks)
+        .into_par_iter()
+        .map(|c| {
+            let mut left = 0;
+            for (s, e) in groups(heads, c * chunk_len, cmp::min(n, (c + 1) * chunk_len)) {
+                if h > 0 && e - s == 1 {
+                    continue;
+                }
+                let group = unsafe { suffixes.range(s, e) };
+                let mut head = s;
+                for (j, &i) in (s..e).zip(group.iter()) {
+                    if flagged(j, NEW_FIRST) {
+                        if j - head > 1 {
+                            left += j - head;
+                        }
+                        head = j;
+                    }
+                    //Suffixes of the first new group keep their rank, except in the first
+                    //round. Every suffix is in a single group.
+                    if h == 0 || head != s {
+                        unsafe { ranks.write(i, head) };
+                    }
+                }
+                if e - head > 1 {
+                    left += e - head;
+                }
+            }
+            left
+        })
+        .sum();
+
+    heads.par_iter().for_each(|flags| {
+        if flags.load(Ordering::Relaxed) & NEW_FIRST != 0 {
+            flags.store(FIRST, Ordering::Relaxed);
+        }
+    });
+    left
+}
+
+/* Sorts a group of suffixes by the given key, flagging the first suffix of every run of equal
+ * keys as the first of a new group. Small groups are sorted along with their keys, large ones
+ * in place on every thread, as their keys would take as much memory as the suffix array.
+ */
+fn sort_group<K>(group: &mut [usize], heads: &[AtomicU8], keyed: &mut Vec<(usize, usize)>, key: K)
+where
+    K: Fn(usize) -> usize + Sync,
+{
+    heads[0].store(FIRST | NEW_FIRST, Ordering::Relaxed);
+    if group.len() > PARALLEL_GROUP {
+        group.par_sort_unstable_by_key(|&i| key(i));
+        heads[1..]
+            .par_iter()
+            .zip(group.par_windows(2))
+            .filter(|(_, w)| key(w[0]) != key(w[1]))
+            .for_each(|(flags, _)| flags.store(NEW_FIRST, Ordering::Relaxed));
+        return;
+    }
+
+    keyed.clear();
+    keyed.extend(group.iter().map(|&i| (key(i), i)));
+    keyed.sort_unstable();
+    for (j, &(k, i)) in keyed.iter().enumerate() {
+        group[j] = i;
+        if j > 0 && k != keyed[j - 1].0 {
+            heads[j].store(NEW_FIRST, Ordering::Relaxed);
+        }
+    }
+}
+
+/* Iterates over the groups whose first suffix lies in SA[start..end] as ranges of the suffix
+ * array, the last of which may end past end. Only the flags marking the first suffix of a
+ * group as of the start of the round are read, which don't change during the round.
+ */
+fn groups(
+    heads: &[AtomicU8],
+    start: usize,
+    end: usize,
+) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let first = move |j: usize| heads[j].load(Ordering::Relaxed) & FIRST != 0;
+    let mut s = start;
+    iter::from_fn(move || {
+        while s < end && !first(s) {
+            s += 1;
+        }
+        if s >= end {
+            return None;
+        }
+        let mut e = s + 1;
+        while e < heads.len() && !first(e) {
+            e += 1;
+        }
+        let group = (s, e);
+        s = e;
+        Some(group)
+    })
+}
+
+/* Computes the lcp array from the permuted lcp array, PLCP[p] being the lcp of the suffix at p
+ * and the suffix preceding it in the suffix array, Φ[p]. PLCP[p + 1] >= PLCP[p] - 1, so the
+ * text is split into a chunk per thread, and every chunk is scanned like in Kasai's algorithm,
+ * only starting from scratch at the start of the chunk. As sentinels are unique, a common
+ * prefix stops short of the first sentinel.
+ *
+ * Φ takes as much memory as the rank array of Kasai's algorithm. As the suffix array is a
+ * permutation every entry of Φ is written exactly once, so threads fill it in place, and it's
+ * dropped before the lcp array is collected from PLCP. The result is identical to the one of
+ * `lcs::lcp_unique_sentinels`.
+ */
+pub fn lcp_unique_sentinels(text: &[u16], pos: &[usize]) -> Vec<isize> {
+    assert_eq!(text.len(), pos.len());
+    let n = text.len();
+
+    //The smallest suffix has no predecessor
+    let mut phi: Vec<usize> = vec![usize::MAX; n];
+    let scatter = Scatter::new(&mut phi);
+    //The suffix array is a permutation, so every suffix has a single predecessor
+    pos.par_windows(2)
+        .for_each(|w| unsafe { scatter.write(w[1], w[0]) });
+
+    let mut plcp: Vec<isize> = vec![0; n];
+    let chunk_len = cmp::max(1, n / rayon::current_num_threads());
+    plcp.par_chunks_mut(chunk_len)
+        .enumerate()
+        .for_each(|(c, chunk)| {
+            let mut l = 0usize;
+            for (i, v) in chunk.iter_mut().enumerate() {
+                let p = c * chunk_len + i;
+                let pred = phi[p];
+                if pred == usize::MAX {
+                    *v = -1;
+                    l = 0;
+                    continue;
+                }
+                while p + l < n && pred + l < n && text[p + l] == text[pred + l] && text[p + l] != 0
+                {
+                    l += 1;
+                }
+                *v = l as isize;
+                l = l.saturating_sub(1);
+            }
+        });
+
+    drop(phi);
+
+    let mut lcp: Vec<isize> = pos.par_iter().map(|&p| plcp[p]).collect();
+    lcp.push(-1);
+    lcp
+}
+
+/* A slice which threads write to at distinct indices, or distinct ranges, without
+ * synchronizing
+ */
+struct Scatter<'a, T> {
+    ptr: *mut T,
+    len: usize,
+    slice: marker::PhantomData<&'a mut [T]>,
+}
+
+unsafe impl<T: Send> Send for Scatter<'_, T> {}
+unsafe impl<T: Send> Sync for Scatter<'_, T> {}
+
+impl<'a, T> Scatter<'a, T> {
+    fn new(slice: &'a mut [T]) -> Scatter<'a, T> {
+        Scatter {
+            ptr: slice.as_mut_ptr(),
+            len: slice.len(),
+            slice: marker::PhantomData,
+        }
+    }
+
+    /* Safe as long as no other thread accesses the same index */
+    unsafe fn write(&self, i: usize, value: T) {
+        assert!(i < self.len);
+        *self.ptr.add(i) = value;
+    }
+
+    /* Safe as long as no other thread accesses the same range while it's borrowed */
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn range(&self, start: usize, end: usize) -> &mut [T] {
+        assert!(start <= end && end <= self.len);
+        slice::from_raw_parts_mut(self.ptr.add(start), end - start)
+    }
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+
+    /* Random documents over a small alphabet, so that they share plenty of substrings, joined
+     * with sentinels the way `Corpus` joins them
+     */
+    fn random_text(state: &mut u32, n_docs: usize, max_len: u32, alphabet: u32) -> Vec<u16> {
+        let mut next = || {
+            *state ^= *state << 13;
+            *state ^= *state >> 17;
+            *state ^= *state << 5;
+            *state
+        };
+        let mut text = Vec::new();
+        for _ in 0..n_docs {
+            let len = next() % (max_len + 1);
+            text.extend((0..len).map(|_| (next() % alphabet) as u16 + 1));
+            text.push(0);
+        }
+        text
+    }
+
+    fn pool(threads: usize) -> rayon::ThreadPool {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn suffix_array_matches_sais() {
+        let (sequential, parallel) = (pool(1), pool(4));
+        let mut state = 0x2545f491u32;
+        for i in 0..500 {
+            let n_docs = [1, 2, 3, 7, 300][i % 5];
+            let text = random_text(&mut state, n_docs, 40, [1, 2, 4, 256][i % 4]);
+            let n_sentinels = n_docs as u16;
+
+            let expected =
+                sequential.install(|| super::super::suffix_array_u16(&text, &n_sentinels));
+            let found = parallel.install(|| suffix_array_u16(&text, &n_sentinels));
+            assert_eq!(expected, found, "text {:?}", text);
+        }
+    }
+
+    #[test]
+    fn lcp_array_matches_kasai() {
+        let (sequential, parallel) = (pool(1), pool(4));
+        let mut state = 0x9e3779b9u32;
+        for i in 0..500 {
+            let n_docs = [1, 2, 3, 7, 300][i % 5];
+            let text = random_text(&mut state, n_docs, 40, [1, 2, 4, 256][i % 4]);
+            let suffix_array =
+                sequential.install(|| super::super::suffix_array_u16(&text, &(n_docs as u16)));
+
+            let expected =
+                sequential.install(|| super::super::lcp_unique_sentinels(&text, &suffix_array));
+            let found = parallel.install(|| lcp_unique_sentinels(&text, &suffix_array));
+            assert_eq!(expected, found, "text {:?}", text);
+        }
+    }
+}
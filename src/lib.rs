@@ -1,94 +1,125 @@
-use bio::data_structures::suffix_array::RawSuffixArray;
-use std::io::{Error, ErrorKind};
+use std::fs;
 use std::*;
-use std::{fs, io};
+mod corpus;
+mod error;
 mod lcs;
+mod query;
+
+pub use corpus::{CommonSubstrings, Corpus};
+pub use error::Error;
+pub use lcs::LcpRmq;
+pub use query::Query;
 
 pub struct ComputeResult<'a> {
+    /* The first occurrence of the substring in every file it was found in */
     pub offsets: Vec<(&'a str, usize)>,
+    /* Every occurrence of the substring, sorted, in every file it was found in */
+    pub occurrences: Vec<(&'a str, Vec<usize>)>,
+    /* The common substring itself */
+    pub bytes: Vec<u8>,
     pub length: usize,
 }
 
-pub fn compute<'a>(files: &[&'a str], data: &Vec<Vec<u16>>, k: u32) -> Result<ComputeResult<'a>, Error> {
-    if data.len() < k as usize {
-        return Err(Error::new(
-            ErrorKind::InvalidInput,
-            format!("You must pass {} files as arguments", k),
-        ));
-    }
-
-    let n_strings = data.len();
-    let total_length = data.iter().map(|s| s.len()).sum();
-    let mut combined: Vec<u16> = Vec::with_capacity(total_length);
-    let mut sentinel_pos: Vec<usize> = Vec::new();
-    let mut file_starts: Vec<usize> = Vec::new();
-
-    for s in data {
-        file_starts.push(combined.len());
-        combined.extend(s);
-        let sent_ind = combined.len() - 1;
-        sentinel_pos.push(sent_ind);
+impl<'a> ComputeResult<'a> {
+    fn new(occurrences: Vec<(&'a str, Vec<usize>)>, bytes: Vec<u8>) -> ComputeResult<'a> {
+        ComputeResult {
+            offsets: occurrences.iter().map(|(f, o)| (*f, o[0])).collect(),
+            occurrences,
+            length: bytes.len(),
+            bytes,
+        }
     }
+}
 
-    let suffix_array: RawSuffixArray = lcs::suffix_array_u16(&combined, &(n_strings as u16));
-    let lcp_array = lcs::lcp_unique_sentinels(&combined, &suffix_array);
-
-    let l0 = lcs::get_l0(&combined, &suffix_array, &k, &sentinel_pos);
-
-    let (delta_ls, delta_rs) =
-        lcs::compute_deltas(&n_strings, &l0, &k, &suffix_array, &sentinel_pos);
-
-    let (maxi, maxv) = lcs::max_min_lcp(&delta_ls, &delta_rs, &lcp_array);
+/* A substring found by `Corpus::contrast`, along with the number of documents it occurs in
+ * outside of and within the background. Its occurrences include the background documents.
+ */
+pub struct Contrast<'a> {
+    pub result: ComputeResult<'a>,
+    pub support: usize,
+    pub background_support: usize,
+}
 
-    let file_offsets = file_offsets_in_delta(
-        &files,
-        &file_starts,
-        &sentinel_pos,
-        &suffix_array,
-        &delta_ls[maxi],
-        &delta_rs[maxi],
-    );
+/* A maximal common substring yielded by `CommonSubstrings` */
+pub type CommonSubstring<'a> = ComputeResult<'a>;
 
-    Ok(ComputeResult {
-        offsets: file_offsets,
-        length: maxv as usize,
-    })
+pub fn compute<'a>(
+    files: &[&'a str],
+    data: &Vec<Vec<u16>>,
+    k: u32,
+) -> Result<ComputeResult<'a>, Error> {
+    compute_occurrences(files, data, k, false)
 }
 
-fn file_offsets_in_delta<'a>(
+/* Same as `compute`, but if full_interval is set the occurrences are taken from the full lcp
+ * interval of the substring rather than the smallest delta range it was found in, so every
+ * occurrence in every file is reported.
+ */
+pub fn compute_occurrences<'a>(
     files: &[&'a str],
-    file_starts: &Vec<usize>,
-    sentinel_pos: &Vec<usize>,
-    suffix_array: &RawSuffixArray,
-    delta_l: &usize,
-    delta_r: &usize,
-) -> Vec<(&'a str, usize)> {
-    let mut file_counts = vec![0usize; files.len()];
-    let mut offsets = vec![0usize; files.len()];
+    data: &Vec<Vec<u16>>,
+    k: u32,
+    full_interval: bool,
+) -> Result<ComputeResult<'a>, Error> {
+    let corpus = Corpus::new(files, data)?;
+    corpus.check_k(k)?;
+    Ok(corpus.lcs_in(files, &Query::new(k), full_interval))
+}
 
-    for i in *delta_l..*delta_r {
-        let suff_ind = suffix_array[i];
-        if let Some(si) = lcs::get_string_index(suff_ind, sentinel_pos) {
-            file_counts[si] += 1;
-            offsets[si] = suff_ind - file_starts[si];
-        }
-    }
+/* Computes the longest common substring for every k from 1 to the number of files, building
+ * the suffix and lcp arrays only once. The result for k is at index k - 1.
+ */
+pub fn compute_all_k<'a>(
+    files: &[&'a str],
+    data: &Vec<Vec<u16>>,
+    full_interval: bool,
+) -> Result<Vec<ComputeResult<'a>>, Error> {
+    let corpus = Corpus::new(files, data)?;
+    Ok((1..=data.len() as u32)
+        .map(|k| corpus.lcs_in(files, &Query::new(k), full_interval))
+        .collect())
+}
 
-    let filenames: Vec<(&str, usize)> = file_counts
-        .iter()
-        .enumerate()
-        .filter(|(_, c)| *c > &0)
-        .map(|(i, _)| (files[i], offsets[i]))
-        .collect();
+/* Finds the n longest distinct common substrings present in at least k files, longest first.
+ * A substring is left out if every one of its occurrences lies within an occurrence of one
+ * already found, so a prefix of one already found is kept if it also occurs elsewhere.
+ */
+pub fn compute_top<'a>(
+    files: &[&'a str],
+    data: &Vec<Vec<u16>>,
+    k: u32,
+    n: usize,
+) -> Result<Vec<ComputeResult<'a>>, Error> {
+    let corpus = Corpus::new(files, data)?;
+    corpus.check_k(k)?;
+    Ok(corpus.top_in(files, &Query::new(k), n))
+}
 
-    filenames
+/* Iterates over all maximal substrings of at least min_length bytes present in at least k
+ * files, that is those which can't be extended by a byte on either side without losing one of
+ * the files they occur in. Every substring is reported once, along with all of its occurrences.
+ */
+pub fn common_substrings<'a>(
+    files: &[&'a str],
+    data: &Vec<Vec<u16>>,
+    k: u32,
+    min_length: usize,
+) -> Result<CommonSubstrings<'a>, Error> {
+    let corpus = Corpus::new(files, data)?;
+    corpus.check_k(k)?;
+    Ok(CommonSubstrings::new(
+        corpus,
+        files.to_vec(),
+        &Query::new(k),
+        min_length,
+    ))
 }
 
 /* Reads the given file and returns a vector of all bytes incremented by one
  * with the 0 sentinel at the end.
  */
-pub fn read_file_and_preprocess(filename: &str) -> Result<Vec<u16>, io::Error> {
-    let f = fs::read(filename)?;
+pub fn read_file_and_preprocess(filename: &str) -> Result<Vec<u16>, Error> {
+    let f = fs::read(filename).map_err(|e| Error::io(filename, e))?;
     let mut shifted_bytes = vec![0u16; f.len() + 1]; //Increment all bytes by one for sentinels
     for i in 0..f.len() {
         shifted_bytes[i] = (f[i] as u16) + 1;
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+/* Describes which substrings a search looks for: those present in at least k documents, which
+ * must include every required document and none of the excluded ones, and at most
+ * max_background of the background documents. Documents are identified by their index in the
+ * corpus, and neither excluded nor background documents count towards k. If documents are
+ * assigned groups, k counts distinct groups instead. If documents are weighted, the documents
+ * a substring is present in must also weigh at least min_weight in total. An exact query only
+ * reports substrings present in exactly k documents. Substrings can also be required to occur
+ * at least min_occurrences times overall, counting every occurrence in every document.
+ *
+ * Instead of an absolute k, a query can be given the fraction of the documents a substring must
+ * be present in, see `Query::fraction`.
+ *
+ * A plain k converts into a query without any constraints, so `corpus.lcs(2)` and
+ * `corpus.lcs(Query::new(2).require(vec![0]))` both work.
+ */
+#[derive(Clone, Debug)]
+pub struct Query {
+    pub(crate) k: u32,
+    pub(crate) required: Vec<usize>,
+    pub(crate) excluded: Vec<usize>,
+    pub(crate) background: Vec<usize>,
+    pub(crate) max_background: usize,
+    pub(crate) groups: Option<Vec<usize>>,
+    pub(crate) weights: Option<Vec<f64>>,
+    pub(crate) min_weight: f64,
+    pub(crate) fraction: Option<f64>,
+    pub(crate) exact: bool,
+    pub(crate) min_occurrences: usize,
+}
+
+impl Query {
+    pub fn new(k: u32) -> Query {
+        Query {
+            k,
+            required: Vec::new(),
+            excluded: Vec::new(),
+            background: Vec::new(),
+            max_background: 0,
+            groups: None,
+            weights: None,
+            min_weight: 0.0,
+            fraction: None,
+            exact: false,
+            min_occurrences: 0,
+        }
+    }
+
+    /* A query for substrings present in at least the given fraction of the documents, between
+     * 0 and 1. k is resolved against the number of documents which count towards it when the
+     * query is run, rounding up, and can be looked up with `Corpus::resolve_k`.
+     */
+    pub fn fraction(fraction: f64) -> Query {
+        Query {
+            fraction: Some(fraction),
+            ..Query::new(1)
+        }
+    }
+
+    /* Only reports substrings present in every one of the given documents */
+    pub fn require<I: IntoIterator<Item = usize>>(mut self, documents: I) -> Query {
+        self.required.extend(documents);
+        self.required.sort();
+        self.required.dedup();
+        self
+    }
+
+    /* Only reports substrings which don't occur anywhere in the given documents */
+    pub fn exclude<I: IntoIterator<Item = usize>>(mut self, documents: I) -> Query {
+        self.excluded.extend(documents);
+        self.excluded.sort();
+        self.excluded.dedup();
+        self
+    }
+
+    /* Only reports substrings which occur in at most max_background of the given documents */
+    pub fn background<I: IntoIterator<Item = usize>>(
+        mut self,
+        documents: I,
+        max_background: usize,
+    ) -> Query {
+        self.background.extend(documents);
+        self.background.sort();
+        self.background.dedup();
+        self.max_background = max_background;
+        self
+    }
+
+    /* Assigns every document a group, given as one label per document in corpus order, so that
+     * a substring only counts as present in k documents if those span k distinct groups.
+     */
+    pub fn groups<I: IntoIterator<Item = usize>>(mut self, labels: I) -> Query {
+        //Number the groups from 0 in order of appearance
+        let mut numbers = HashMap::new();
+        let groups = labels
+            .into_iter()
+            .map(|label| {
+                let next = numbers.len();
+                *numbers.entry(label).or_insert(next)
+            })
+            .collect();
+        self.groups = Some(groups);
+        self
+    }
+
+    /* Weighs every document, given as one weight per document in corpus order, and only
+     * reports substrings present in documents whose weights sum to at least min_weight. Weights
+     * must not be negative.
+     */
+    pub fn weights<I: IntoIterator<Item = f64>>(mut self, weights: I, min_weight: f64) -> Query {
+        self.weights = Some(weights.into_iter().collect());
+        self.min_weight = min_weight;
+        self
+    }
+
+    /* Only reports substrings present in exactly k documents rather than at least k */
+    pub fn exact(mut self) -> Query {
+        self.exact = true;
+        self
+    }
+
+    /* Only reports substrings occurring at least min_occurrences times across all documents */
+    pub fn min_occurrences(mut self, min_occurrences: usize) -> Query {
+        self.min_occurrences = min_occurrences;
+        self
+    }
+
+    /* Whether every candidate substring has to be checked against all of its occurrences */
+    pub(crate) fn checks_intervals(&self) -> bool {
+        !self.background.is_empty() || self.exact
+    }
+
+    /* The group of each of the given number of documents, which is a group of its own if
+     * none were assigned.
+     */
+    pub(crate) fn group_of(&self, documents: usize) -> Vec<usize> {
+        match &self.groups {
+            Some(groups) => groups.clone(),
+            None => (0..documents).collect(),
+        }
+    }
+
+    /* The documents which don't count towards k */
+    pub(crate) fn uncounted(&self) -> Vec<usize> {
+        self.excluded
+            .iter()
+            .chain(&self.background)
+            .cloned()
+            .collect()
+    }
+}
+
+impl From<u32> for Query {
+    fn from(k: u32) -> Query {
+        Query::new(k)
+    }
+}